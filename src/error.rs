@@ -6,6 +6,22 @@ pub enum RxError {
     Corrupted([u8; 7]),
     PortError(serialport::Error),
     TimedOut,
+    /// The lidar answered with an unexpected response type
+    UnexpectedResponse(u8),
+    /// The lidar refused a configuration entry (type, result)
+    ConfRejected(u32, u32),
+    /// The lidar answered a configuration request for another entry (expected, received)
+    ConfMismatch(u32, u32),
+    /// The configuration entry describes a scan mode, but no mode id was given (type)
+    MissingScanMode(u32),
+    /// The lidar does not list the requested scan mode
+    UnknownScanMode,
+    /// The request cannot be made while a scan is running
+    ScanInProgress,
+    /// The lidar reports an error state (error code)
+    DeviceError(u16),
+    /// No serial port matches the requested USB device
+    PortNotFound,
 }
 
 impl From<serialport::Error> for RxError {
@@ -26,6 +42,22 @@ impl fmt::Display for RxError {
             RxError::Corrupted(v) => { write!(f, "CORRUPTED! {:x?}", v) }
             RxError::PortError(err) => { write!(f, "Port error: {}", err) }
             RxError::TimedOut => { write!(f, "Timed out waiting for data") }
+            RxError::UnexpectedResponse(t) => { write!(f, "Unexpected response type {:#04x}", t) }
+            RxError::ConfRejected(conf_type, result) => {
+                write!(f, "Configuration {:#x} rejected (result {})", conf_type, result)
+            }
+            RxError::ConfMismatch(expected, received) => {
+                write!(f, "Expected an answer for configuration {:#x}, got {:#x}", expected, received)
+            }
+            RxError::MissingScanMode(conf_type) => {
+                write!(f, "Configuration {:#x} needs a scan mode id", conf_type)
+            }
+            RxError::UnknownScanMode => { write!(f, "The lidar does not support this scan mode") }
+            RxError::ScanInProgress => { write!(f, "A scan is in progress") }
+            RxError::DeviceError(code) => { write!(f, "Lidar reports error {:#06x}", code) }
+            RxError::PortNotFound => { write!(f, "No matching serial port found") }
         }
     }
-}
\ No newline at end of file
+}
+
+impl std::error::Error for RxError {}
@@ -1,92 +1,297 @@
-use crate::laser::Lidar;
+use crate::laser::{discovery, Clock, Lidar, MonotonicClock, Sample, ScanMode, ScanSession};
+use crate::render::{colour, Image, Renderer};
+use crate::scan::Point2;
 use clap::Parser;
-use palette::{Mix, Srgb};
-use show_image::{create_window, event, run_context, ImageInfo, ImageView, WindowOptions};
+use show_image::event::{ElementState, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+use show_image::{create_window, run_context, ImageInfo, ImageView, WindowOptions};
+use std::collections::VecDeque;
 use std::error::Error;
-use tqdm::Iter;
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
+use std::time::Duration;
+
+/// Time between redraws
+const FRAME_TIME: Duration = Duration::from_millis(33);
+/// Zoom factor per key press or wheel notch
+const ZOOM: f64 = 1.25;
+/// Share of the view a pan key press moves by
+const PAN: f64 = 0.1;
+/// Bounds of the point decay
+const MIN_DECAY: Duration = Duration::from_millis(50);
+const MAX_DECAY: Duration = Duration::from_secs(30);
+/// Most points kept, bounding memory and redraw time of long decays at high sample rates
+const MAX_POINTS: usize = 1 << 18;
+/// Smallest distance between range rings on screen, in pixels
+const GRID_PIXELS: f64 = 80.0;
+const GRID_COLOUR: [u8; 3] = [0x30, 0x30, 0x30];
+
+const HELP: &str = "\
+Keys: +/- or wheel zoom, arrows pan, 0 reset view, space pause,
+      [/] shorten/lengthen decay, g grid, m switch scan mode, Esc quit";
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
-    port: String,
-    points: Option<usize>,
+    /// serial port of the lidar, discovered if omitted
+    port: Option<String>,
+    /// window width in pixels
+    #[arg(long, default_value_t = 1920)]
+    width: u32,
+    /// window height in pixels
+    #[arg(long, default_value_t = 1080)]
+    height: u32,
+    /// initial scale in millimetres per pixel
+    #[arg(long, default_value_t = 5.0)]
+    scale: f64,
+    /// seconds a point stays visible while fading out
+    #[arg(long, default_value_t = 1.0)]
+    decay: f64,
+    /// start with a dense (express) scan
+    #[arg(long)]
+    dense: bool,
+    /// hide the range grid
+    #[arg(long)]
+    no_grid: bool,
 }
 
 /// Displays a live feed of the lidar stream
 pub fn live_view() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
 
-    let mut lidar = Lidar::init(args.port).expect("Lidar should have initialized");
+    let mut lidar = match &args.port {
+        Some(port) => Lidar::init(port.clone())?,
+        None => discovery::discover()?.first().ok_or("No lidar found")?.open()?,
+    };
 
     // status information
-    let info = lidar.get_info();
-    let health = lidar.get_health();
+    let info = lidar.get_info()?;
+    let health = lidar.get_health()?;
 
     println!("\nModel {} version {}.{} HW {}", info.model, info.firmware_version >> 8, info.firmware_version & 0xff, info.hardware_version);
 
     if health.status > 0 {
         eprintln!(" code: {}\nexiting!", health.error_code);
-        lidar.reset();
+        lidar.reset()?;
         return Ok(());
     }
 
-    /// number of samples
-    let n: usize = args.points.unwrap_or(5000);
+    // read/display loop
+    run_context(move || {
+        if let Err(err) = LiveView::new(lidar, &args).run() {
+            eprintln!("Live view failed: {}", err);
+            std::process::exit(1);
+        }
+    });
+}
 
-    // window dimensions
-    const WIDTH: usize = 1920;
-    const HEIGHT: usize = 1080;
-    let mut pixel_data: Box<[u8]> = vec![0u8; WIDTH * HEIGHT * 3].into_boxed_slice();
+/// State of the live view window
+struct LiveView {
+    // the session must be dropped (stopped) before a new one starts
+    session: Option<ScanSession>,
+    lidar: Lidar,
+    mode: ScanMode,
+    renderer: Renderer,
+    /// scale to return to when the view is reset
+    home_scale: f64,
+    image: Image,
+    /// samples still visible, oldest first, at most `MAX_POINTS`
+    points: VecDeque<Sample>,
+    decay: Duration,
+    grid: bool,
+    /// display time frozen while paused
+    paused: Option<Duration>,
+}
 
-    // color interpolation
-    let high: Srgb<f32> = Srgb::from(0xf3ff82).into();
-    let low: Srgb<f32> = Srgb::from(0x1e4160).into();
+impl LiveView {
+    fn new(lidar: Lidar, args: &Args) -> LiveView {
+        let renderer = Renderer::new(args.width, args.height, args.scale / 1000.0);
+        LiveView {
+            session: None,
+            lidar,
+            mode: if args.dense { ScanMode::Dense } else { ScanMode::Standard },
+            renderer,
+            home_scale: renderer.scale,
+            image: renderer.blank(),
+            points: VecDeque::new(),
+            decay: Duration::from_secs_f64(args.decay.max(0.0)).clamp(MIN_DECAY, MAX_DECAY),
+            grid: !args.no_grid,
+            paused: None,
+        }
+    }
 
-    // read/display loop
-    run_context(move || {
-        println!("Starting scan ({} sample{})...", n, if n == 1 { "" } else { "s" });
-        let rx = lidar.start_scan().expect("Scan should have started");
+    fn run(mut self) -> Result<(), Box<dyn Error>> {
         let window = create_window("scan", WindowOptions {
-            // size: Some([WIDTH as u32 * 4 / 5, HEIGHT as u32 * 4 / 5]),
-            fullscreen: false,
+            size: Some([self.renderer.width, self.renderer.height]),
+            // zooming and panning re-render the scan instead of scaling the image
+            default_controls: false,
             ..WindowOptions::default()
-        }).unwrap();
-
-        // generate pixel data from samples
-        println!("Scanning...");
-        for (i, sample) in rx.iter().take(n).enumerate().tqdm() {
-            let raw_x = (sample.angle as f64).to_radians().cos() * sample.distance as f64;
-            let raw_y = (sample.angle as f64).to_radians().sin() * sample.distance as f64;
-            let x = ((raw_x / 5f64) as isize + (WIDTH as isize / 2)) as usize;
-            let y = ((raw_y / 5f64) as isize + (HEIGHT as isize / 2)) as usize;
-
-            if x < WIDTH && y < HEIGHT {
-                let pos = (y * WIDTH + x) * 3;
-                let pixel: [f32; 3] = low.mix(high, (sample.intensity as f32) / 64.0).into();
-                pixel_data[pos] = (pixel[2] * 255f32) as u8;
-                pixel_data[pos + 1] = (pixel[1] * 255f32) as u8;
-                pixel_data[pos + 2] = (pixel[0] * 255f32) as u8;
+        })?;
+        let events = window.event_channel()?;
+
+        println!("{}", HELP);
+        self.start();
+
+        loop {
+            self.receive();
+            self.redraw();
+            let image = ImageView::new(ImageInfo::rgb8(self.image.width, self.image.height), &self.image.pixels);
+            window.set_image("scan", image)?;
+
+            let mut event = match events.recv_timeout(FRAME_TIME) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            };
+            loop {
+                if !self.handle(event) {
+                    return Ok(());
+                }
+                event = match events.try_recv() {
+                    Ok(event) => event,
+                    Err(_) => break,
+                };
             }
+        }
+    }
 
-            if i % 360 == 0 {
-                // display
-                let image = ImageView::new(ImageInfo::bgr8(WIDTH as u32, HEIGHT as u32), pixel_data.as_ref());
-                window.set_image(format!("image-{}", i), image).unwrap();
+    /// Starts a scan in the selected mode, dropping the points of the previous one
+    fn start(&mut self) {
+        self.session = None;
+        self.points.clear();
+        match self.lidar.start_scan_mode(self.mode) {
+            Ok(session) => {
+                println!("Scanning ({:?})...", self.mode);
+                self.session = Some(session);
             }
+            Err(err) => eprintln!("Unable to start {:?} scan: {}", self.mode, err),
         }
+    }
 
-        println!("\nStopping scan...");
-        lidar.stop(false);
-        lidar.join();
+    /// Takes in everything buffered so far
+    fn receive(&mut self) {
+        let Some(session) = &self.session else {
+            return;
+        };
+        loop {
+            match session.receiver().try_recv() {
+                Ok(sample) => {
+                    if self.paused.is_none() && sample.is_valid() {
+                        if self.points.len() == MAX_POINTS {
+                            self.points.pop_front();
+                        }
+                        self.points.push_back(sample);
+                    }
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    match session.error() {
+                        Some(err) => eprintln!("Scan ended: {}", err),
+                        None => eprintln!("Scan ended"),
+                    }
+                    self.session = None;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn redraw(&mut self) {
+        let now = self.paused.unwrap_or_else(|| MonotonicClock.now());
+        while self.points.front().is_some_and(|p| p.timestamp + self.decay < now) {
+            self.points.pop_front();
+        }
+
+        self.image.fill(self.renderer.background);
+        if self.grid {
+            let spacing = self.grid_spacing();
+            self.renderer.draw_grid(&mut self.image, spacing, GRID_COLOUR);
+        }
+
+        let background = self.renderer.background;
+        for sample in &self.points {
+            // fade from the intensity colour to the background as the point ages
+            let age = now.saturating_sub(sample.timestamp).as_secs_f64() / self.decay.as_secs_f64();
+            let fade = (1.0 - age).clamp(0.0, 1.0);
+            let colour = colour(sample);
+            let colour = [0, 1, 2].map(|i| (background[i] as f64 + (colour[i] as f64 - background[i] as f64) * fade) as u8);
+            self.renderer.plot(&mut self.image, sample, colour);
+        }
+    }
 
-        // listen for ESC
-        for event in window.event_channel().unwrap() {
-            if let event::WindowEvent::KeyboardInput(event) = event {
-                if event.input.key_code == Some(event::VirtualKeyCode::Escape) && event.input.state.is_pressed() {
-                    break;
+    /// Ring spacing of 1, 2 or 5 times a power of ten, keeping rings apart on screen
+    fn grid_spacing(&self) -> f64 {
+        let min = GRID_PIXELS * self.renderer.scale;
+        let magnitude = 10f64.powf(min.log10().floor());
+        [1.0, 2.0, 5.0, 10.0]
+            .into_iter()
+            .map(|step| step * magnitude)
+            .find(|&spacing| spacing >= min)
+            .unwrap_or(10.0 * magnitude)
+    }
+
+    fn zoom(&mut self, factor: f64) {
+        self.renderer.scale /= factor;
+    }
+
+    /// Moves the view by a share of its width or height
+    fn pan(&mut self, right: f64, up: f64) {
+        let renderer = &mut self.renderer;
+        renderer.centre.x += right * renderer.width as f64 * renderer.scale;
+        renderer.centre.y += up * renderer.height as f64 * renderer.scale;
+    }
+
+    /// Reacts to a window event, returning whether to keep running
+    fn handle(&mut self, event: WindowEvent) -> bool {
+        match event {
+            WindowEvent::CloseRequested(_) | WindowEvent::Destroyed(_) => return false,
+            WindowEvent::MouseWheel(wheel) => {
+                let notches = match wheel.delta {
+                    MouseScrollDelta::LineDelta(_, y) => y as f64,
+                    MouseScrollDelta::PixelDelta(position) => position.y / 40.0,
+                };
+                self.zoom(ZOOM.powf(notches));
+            }
+            WindowEvent::KeyboardInput(event) if event.input.state == ElementState::Pressed => {
+                let Some(key) = event.input.key_code else {
+                    return true;
+                };
+                match key {
+                    VirtualKeyCode::Escape | VirtualKeyCode::Q => return false,
+                    VirtualKeyCode::Plus | VirtualKeyCode::Equals | VirtualKeyCode::NumpadAdd => self.zoom(ZOOM),
+                    VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract => self.zoom(1.0 / ZOOM),
+                    VirtualKeyCode::Left => self.pan(-PAN, 0.0),
+                    VirtualKeyCode::Right => self.pan(PAN, 0.0),
+                    VirtualKeyCode::Up => self.pan(0.0, PAN),
+                    VirtualKeyCode::Down => self.pan(0.0, -PAN),
+                    VirtualKeyCode::Key0 | VirtualKeyCode::Home => {
+                        self.renderer.scale = self.home_scale;
+                        self.renderer.centre = Point2::new(0.0, 0.0);
+                    }
+                    VirtualKeyCode::Space => {
+                        self.paused = match self.paused {
+                            Some(_) => None,
+                            None => Some(MonotonicClock.now()),
+                        };
+                        println!("{}", if self.paused.is_some() { "Paused" } else { "Resumed" });
+                    }
+                    VirtualKeyCode::LBracket | VirtualKeyCode::RBracket => {
+                        let decay = if key == VirtualKeyCode::LBracket { self.decay / 2 } else { self.decay * 2 };
+                        self.decay = decay.clamp(MIN_DECAY, MAX_DECAY);
+                        println!("Decay: {:.2} s", self.decay.as_secs_f64());
+                    }
+                    VirtualKeyCode::G => self.grid = !self.grid,
+                    VirtualKeyCode::M => {
+                        self.mode = match self.mode {
+                            ScanMode::Standard => ScanMode::Dense,
+                            ScanMode::Dense => ScanMode::Standard,
+                        };
+                        self.paused = None;
+                        self.start();
+                    }
+                    _ => {}
                 }
             }
+            _ => {}
         }
-    });
+        true
+    }
 }
-
@@ -1,17 +1,17 @@
-use crate::laser::cmd::ScanModeConfEntry;
+use crate::laser::cmd::ConfEntry;
 use crate::laser::Lidar;
 use std::error::Error;
 
 pub mod live;
 
 pub fn print_modes(mut lidar: &mut Lidar) -> Result<(), Box<dyn Error>> {
-    let modes = u16::from_le_bytes(lidar.get_lidar_conf(ScanModeConfEntry::Count, None).payload.try_into().unwrap());
-    let typical = u16::from_le_bytes(lidar.get_lidar_conf(ScanModeConfEntry::Typical, None).payload.try_into().unwrap());
+    let modes = u16::from_le_bytes(lidar.get_lidar_conf(ConfEntry::Count, None)?.payload.try_into().unwrap());
+    let typical = u16::from_le_bytes(lidar.get_lidar_conf(ConfEntry::Typical, None)?.payload.try_into().unwrap());
     println!("Modes: {}\nTypical: {}\n", modes, typical);
     for i in 0..modes {
-        let name = String::from_utf8(lidar.get_lidar_conf(ScanModeConfEntry::Name, Some(i)).payload).unwrap();
-        let us_per_sample = u32::from_le_bytes(lidar.get_lidar_conf(ScanModeConfEntry::UsPerSample, Some(i)).payload.try_into().unwrap()) / (1 << 8);
-        let max_distance = u32::from_le_bytes(lidar.get_lidar_conf(ScanModeConfEntry::MaxDistance, Some(i)).payload.try_into().unwrap()) / (1 << 8);
+        let name = String::from_utf8(lidar.get_lidar_conf(ConfEntry::Name, Some(i))?.payload).unwrap();
+        let us_per_sample = u32::from_le_bytes(lidar.get_lidar_conf(ConfEntry::UsPerSample, Some(i))?.payload.try_into().unwrap()) / (1 << 8);
+        let max_distance = u32::from_le_bytes(lidar.get_lidar_conf(ConfEntry::MaxDistance, Some(i))?.payload.try_into().unwrap()) / (1 << 8);
         // let ans_type = match u32::from_le_bytes(lidar.get_lidar_conf(MaxDistance, Some(i)).payload.try_into().unwrap()) / (1 << 8) {
         //     0x81 => Measurement,
         //     0x82 => MeasurementCapsuled,
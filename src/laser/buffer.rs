@@ -0,0 +1,415 @@
+use crate::laser::protocol::Sample;
+use crate::laser::stats::ScanStats;
+use std::collections::VecDeque;
+use std::sync::mpsc::{RecvError, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// What the reader thread does with new samples when the buffer is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Wait for the consumer to catch up (the lidar's own buffers may overflow instead)
+    Block,
+    /// Discard the oldest buffered sample
+    DropOldest,
+    /// Discard the incoming sample
+    DropNewest,
+    /// Keep only the latest complete revolution and the one being received
+    LatestRevolution,
+}
+
+/// Size and overflow behaviour of the buffer between reader thread and consumer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferConfig {
+    /// Maximum number of buffered samples
+    pub capacity: usize,
+    pub policy: DropPolicy,
+}
+
+impl Default for BufferConfig {
+    /// A few revolutions' worth of samples, dropping the oldest on overflow
+    fn default() -> Self {
+        BufferConfig {
+            capacity: 8192,
+            policy: DropPolicy::DropOldest,
+        }
+    }
+}
+
+struct State {
+    samples: VecDeque<Sample>,
+    /// Index of the last revolution start in `samples`
+    last_start: Option<usize>,
+    /// Sender or receiver is gone
+    closed: bool,
+}
+
+impl State {
+    fn pop_front(&mut self) -> Option<Sample> {
+        let sample = self.samples.pop_front()?;
+        self.last_start = match self.last_start {
+            Some(0) | None => None,
+            Some(i) => Some(i - 1),
+        };
+        Some(sample)
+    }
+}
+
+struct Shared {
+    config: BufferConfig,
+    state: Mutex<State>,
+    /// Signalled when samples are pushed or the buffer is closed
+    ready: Condvar,
+    /// Signalled when samples are popped or the buffer is closed
+    space: Condvar,
+    stats: Arc<ScanStats>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.ready.notify_all();
+        self.space.notify_all();
+    }
+}
+
+/// Closes a buffer from outside its two halves, waking a sender blocked on a full buffer
+pub(crate) struct Closer {
+    shared: Arc<Shared>,
+}
+
+impl Closer {
+    pub(crate) fn close(&self) {
+        self.shared.close();
+    }
+}
+
+/// Creates a bounded sample buffer
+pub fn bounded(config: BufferConfig) -> (ScanSender, ScanReceiver) {
+    let shared = Arc::new(Shared {
+        config: BufferConfig {
+            capacity: config.capacity.max(1),
+            ..config
+        },
+        state: Mutex::new(State {
+            samples: VecDeque::new(),
+            last_start: None,
+            closed: false,
+        }),
+        ready: Condvar::new(),
+        space: Condvar::new(),
+        stats: Arc::new(ScanStats::default()),
+    });
+
+    (
+        ScanSender { shared: Arc::clone(&shared) },
+        ScanReceiver { shared },
+    )
+}
+
+/// Producing half of the sample buffer, owned by the reader thread
+pub struct ScanSender {
+    shared: Arc<Shared>,
+}
+
+impl ScanSender {
+    /// Buffers a sample according to the drop policy.
+    ///
+    /// Fails if the receiver has been dropped.
+    pub fn send(&self, sample: Sample) -> Result<(), Sample> {
+        let shared = &self.shared;
+        let capacity = shared.config.capacity;
+        let mut state = shared.lock();
+
+        if state.closed {
+            return Err(sample);
+        }
+
+        match shared.config.policy {
+            DropPolicy::Block => {
+                while state.samples.len() >= capacity && !state.closed {
+                    state = shared.space.wait(state).unwrap_or_else(|e| e.into_inner());
+                }
+                if state.closed {
+                    return Err(sample);
+                }
+            }
+            DropPolicy::DropNewest => {
+                if state.samples.len() >= capacity {
+                    shared.stats.add_dropped(1);
+                    return Ok(());
+                }
+            }
+            DropPolicy::DropOldest | DropPolicy::LatestRevolution => {
+                if shared.config.policy == DropPolicy::LatestRevolution && sample.start {
+                    // a revolution just completed, anything before it is stale
+                    if let Some(start) = state.last_start {
+                        state.samples.drain(..start);
+                        state.last_start = Some(0);
+                        shared.stats.add_dropped(start as u64);
+                    }
+                }
+                if state.samples.len() >= capacity {
+                    state.pop_front();
+                    shared.stats.add_dropped(1);
+                }
+            }
+        }
+
+        if sample.start {
+            state.last_start = Some(state.samples.len());
+        }
+        state.samples.push_back(sample);
+        drop(state);
+        shared.ready.notify_one();
+
+        Ok(())
+    }
+
+    /// Counters of the scan this buffer belongs to
+    pub fn stats(&self) -> &Arc<ScanStats> {
+        &self.shared.stats
+    }
+
+    /// Whether the receiver has been dropped
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().closed
+    }
+}
+
+impl Drop for ScanSender {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+/// Receiving half of the sample buffer returned when a scan is started
+pub struct ScanReceiver {
+    shared: Arc<Shared>,
+}
+
+impl ScanReceiver {
+    /// Blocks until a sample is available.
+    ///
+    /// Fails once the scan has ended and the buffer is drained.
+    pub fn recv(&self) -> Result<Sample, RecvError> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(sample) = state.pop_front() {
+                drop(state);
+                self.shared.space.notify_one();
+                return Ok(sample);
+            }
+            if state.closed {
+                return Err(RecvError);
+            }
+            state = self.shared.ready.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Returns a buffered sample without blocking
+    pub fn try_recv(&self) -> Result<Sample, TryRecvError> {
+        let mut state = self.shared.lock();
+        match state.pop_front() {
+            Some(sample) => {
+                drop(state);
+                self.shared.space.notify_one();
+                Ok(sample)
+            }
+            None if state.closed => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Waits at most `timeout` for a sample
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Sample, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(sample) = state.pop_front() {
+                drop(state);
+                self.shared.space.notify_one();
+                return Ok(sample);
+            }
+            if state.closed {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .shared
+                .ready
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Iterates over samples until the scan ends
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { rx: self }
+    }
+
+    /// Number of samples currently buffered
+    pub fn len(&self) -> usize {
+        self.shared.lock().samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of samples discarded by the drop policy so far
+    pub fn dropped(&self) -> u64 {
+        self.shared.stats.dropped()
+    }
+
+    /// Counters of the scan feeding this receiver
+    pub fn stats(&self) -> &Arc<ScanStats> {
+        &self.shared.stats
+    }
+
+    pub(crate) fn closer(&self) -> Closer {
+        Closer {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl Drop for ScanReceiver {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+impl<'a> IntoIterator for &'a ScanReceiver {
+    type Item = Sample;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl IntoIterator for ScanReceiver {
+    type Item = Sample;
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter { rx: self }
+    }
+}
+
+/// Blocking iterator over a [`ScanReceiver`]
+pub struct Iter<'a> {
+    rx: &'a ScanReceiver,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        self.rx.recv().ok()
+    }
+}
+
+/// Owning blocking iterator over a [`ScanReceiver`]
+pub struct IntoIter {
+    rx: ScanReceiver,
+}
+
+impl Iterator for IntoIter {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        self.rx.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::laser::protocol::tests::node;
+    use std::thread;
+
+    /// A sample that can be told apart by its distance
+    fn sample(start: bool, id: u16) -> Sample {
+        Sample::from_node(&node(start, 0, 0, id)).unwrap()
+    }
+
+    fn ids(rx: &ScanReceiver) -> Vec<u32> {
+        let mut ids = Vec::new();
+        while let Ok(sample) = rx.try_recv() {
+            ids.push(sample.distance_q2);
+        }
+        ids
+    }
+
+    fn buffer(capacity: usize, policy: DropPolicy) -> (ScanSender, ScanReceiver) {
+        bounded(BufferConfig { capacity, policy })
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_newest() {
+        let (tx, rx) = buffer(3, DropPolicy::DropOldest);
+        for id in 1..=5 {
+            tx.send(sample(false, id)).unwrap();
+        }
+        assert_eq!(ids(&rx), [3, 4, 5]);
+        assert_eq!(rx.dropped(), 2);
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_oldest() {
+        let (tx, rx) = buffer(3, DropPolicy::DropNewest);
+        for id in 1..=5 {
+            tx.send(sample(false, id)).unwrap();
+        }
+        assert_eq!(ids(&rx), [1, 2, 3]);
+        assert_eq!(rx.dropped(), 2);
+    }
+
+    #[test]
+    fn latest_revolution_drops_older_revolutions() {
+        let (tx, rx) = buffer(100, DropPolicy::LatestRevolution);
+        for id in 1..=7 {
+            // revolutions start at 1, 4 and 7
+            tx.send(sample(id % 3 == 1, id)).unwrap();
+        }
+        assert_eq!(ids(&rx), [4, 5, 6, 7]);
+        assert_eq!(rx.dropped(), 3);
+    }
+
+    #[test]
+    fn block_waits_for_the_consumer() {
+        let (tx, rx) = buffer(2, DropPolicy::Block);
+        let sender = thread::spawn(move || {
+            for id in 1..=3 {
+                tx.send(sample(false, id)).unwrap();
+            }
+        });
+
+        assert_eq!(rx.recv_timeout(Duration::from_secs(1)).unwrap().distance_q2, 1);
+        sender.join().unwrap();
+        assert_eq!(ids(&rx), [2, 3]);
+        assert_eq!(rx.dropped(), 0);
+    }
+
+    #[test]
+    fn closing_wakes_a_blocked_sender() {
+        let (tx, rx) = buffer(1, DropPolicy::Block);
+        tx.send(sample(false, 1)).unwrap();
+        let closer = rx.closer();
+        let sender = thread::spawn(move || tx.send(sample(false, 2)).is_err());
+
+        thread::sleep(Duration::from_millis(20));
+        closer.close();
+        assert!(sender.join().unwrap());
+    }
+}
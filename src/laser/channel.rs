@@ -0,0 +1,357 @@
+use serialport::SerialPort;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+/// Default TCP port of Slamtec network lidars
+pub const DEFAULT_TCP_PORT: u16 = 20108;
+/// Default UDP port of Slamtec network lidars
+pub const DEFAULT_UDP_PORT: u16 = 8089;
+
+/// Whether a read failed only because no data arrived in time
+pub(crate) fn is_timeout(err: &io::Error) -> bool {
+    matches!(err.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+}
+
+/// A bidirectional byte stream to a lidar
+pub trait Channel: Read + Write + Send {
+    /// Opens a second handle to the same stream, for use by the reader thread
+    fn try_clone(&self) -> io::Result<Box<dyn Channel>>;
+
+    /// Sets how long reads may block before failing with `TimedOut`
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+
+    /// Drives the DTR line, which switches the motor of A series lidars on USB adapters
+    fn set_dtr(&mut self, _level: bool) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl Channel for Box<dyn SerialPort> {
+    fn try_clone(&self) -> io::Result<Box<dyn Channel>> {
+        Ok(Box::new(SerialPort::try_clone(self.as_ref())?))
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        Ok(SerialPort::set_timeout(self.as_mut(), timeout)?)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        Ok(self.write_data_terminal_ready(level)?)
+    }
+}
+
+/// Lidar connected over TCP (e.g. S1/S2E TCP variants)
+pub struct TcpChannel {
+    stream: TcpStream,
+}
+
+impl TcpChannel {
+    /// Connects to a lidar listening on `addr`
+    pub fn connect(addr: impl ToSocketAddrs, timeout: Duration) -> io::Result<TcpChannel> {
+        let mut last_err = io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to");
+        for addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => {
+                    stream.set_nodelay(true)?;
+                    stream.set_read_timeout(Some(timeout))?;
+                    return Ok(TcpChannel { stream });
+                }
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err)
+    }
+}
+
+impl Read for TcpChannel {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TcpChannel {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Channel for TcpChannel {
+    fn try_clone(&self) -> io::Result<Box<dyn Channel>> {
+        Ok(Box::new(TcpChannel { stream: self.stream.try_clone()? }))
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.stream.set_read_timeout(Some(timeout))
+    }
+}
+
+/// Lidar connected over UDP
+///
+/// Datagrams are buffered so that they can be consumed as a byte stream.
+pub struct UdpChannel {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+    pos: usize,
+}
+
+impl UdpChannel {
+    /// Binds an ephemeral local port and associates it with the lidar at `addr`
+    pub fn connect(addr: impl ToSocketAddrs, timeout: Duration) -> io::Result<UdpChannel> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.connect(addr)?;
+        socket.set_read_timeout(Some(timeout))?;
+        Ok(UdpChannel::from_socket(socket))
+    }
+
+    fn from_socket(socket: UdpSocket) -> UdpChannel {
+        UdpChannel {
+            socket,
+            buffer: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for UdpChannel {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buffer.len() {
+            // a datagram must be received in one go, or the remainder is lost
+            self.buffer.resize(u16::MAX as usize, 0);
+            self.pos = 0;
+            match self.socket.recv(&mut self.buffer) {
+                Ok(len) => self.buffer.truncate(len),
+                Err(err) => {
+                    self.buffer.clear();
+                    return Err(err);
+                }
+            }
+        }
+
+        let len = buf.len().min(self.buffer.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buffer[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl Write for UdpChannel {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.socket.send(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Channel for UdpChannel {
+    fn try_clone(&self) -> io::Result<Box<dyn Channel>> {
+        Ok(Box::new(UdpChannel::from_socket(self.socket.try_clone()?)))
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.socket.set_read_timeout(Some(timeout))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::laser::cmd::ConfEntry::MacAddr;
+    use crate::laser::protocol::conf_req;
+    use crate::laser::Lidar;
+    use std::collections::VecDeque;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Condvar, Mutex};
+    use std::thread;
+    use std::time::Instant;
+
+    /// A lidar played by a test: each request is answered by `respond`, and further data can be
+    /// pushed at any time. All clones share the same stream.
+    #[derive(Clone)]
+    pub(crate) struct ScriptedChannel {
+        script: Arc<Script>,
+        timeout: Duration,
+    }
+
+    /// Answers a request with the bytes the lidar sends back
+    type Respond = Box<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>;
+
+    struct Script {
+        incoming: Mutex<VecDeque<u8>>,
+        arrived: Condvar,
+        written: Mutex<Vec<Vec<u8>>>,
+        /// reads hit the end of the stream once `incoming` runs dry
+        closed: AtomicBool,
+        dtr: Mutex<Option<bool>>,
+        respond: Respond,
+    }
+
+    impl ScriptedChannel {
+        pub(crate) fn new(respond: impl Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static) -> ScriptedChannel {
+            ScriptedChannel {
+                script: Arc::new(Script {
+                    incoming: Mutex::new(VecDeque::new()),
+                    arrived: Condvar::new(),
+                    written: Mutex::new(Vec::new()),
+                    closed: AtomicBool::new(false),
+                    dtr: Mutex::new(None),
+                    respond: Box::new(respond),
+                }),
+                timeout: Duration::from_secs(1),
+            }
+        }
+
+        /// Makes `data` available to reads
+        pub(crate) fn push(&self, data: &[u8]) {
+            self.script.incoming.lock().unwrap().extend(data);
+            self.script.arrived.notify_all();
+        }
+
+        /// Ends the stream, as a disconnected adapter does
+        pub(crate) fn close(&self) {
+            self.script.closed.store(true, Ordering::Relaxed);
+            self.script.arrived.notify_all();
+        }
+
+        /// Everything written so far, one entry per write
+        pub(crate) fn written(&self) -> Vec<Vec<u8>> {
+            self.script.written.lock().unwrap().clone()
+        }
+
+        /// The last level the DTR line was driven to
+        pub(crate) fn dtr(&self) -> Option<bool> {
+            *self.script.dtr.lock().unwrap()
+        }
+    }
+
+    impl Read for ScriptedChannel {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let deadline = Instant::now() + self.timeout;
+            let mut incoming = self.script.incoming.lock().unwrap();
+            while incoming.is_empty() {
+                if self.script.closed.load(Ordering::Relaxed) {
+                    return Ok(0);
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+                incoming = self.script.arrived.wait_timeout(incoming, deadline - now).unwrap().0;
+            }
+
+            let len = buf.len().min(incoming.len());
+            for (byte, data) in buf.iter_mut().zip(incoming.drain(..len)) {
+                *byte = data;
+            }
+            Ok(len)
+        }
+    }
+
+    impl Write for ScriptedChannel {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.script.written.lock().unwrap().push(buf.to_vec());
+            self.push(&(self.script.respond)(buf));
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Channel for ScriptedChannel {
+        fn try_clone(&self) -> io::Result<Box<dyn Channel>> {
+            Ok(Box::new(self.clone()))
+        }
+
+        fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+            self.timeout = timeout;
+            Ok(())
+        }
+
+        fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+            *self.script.dtr.lock().unwrap() = Some(level);
+            Ok(())
+        }
+    }
+
+    const INFO_REQ: [u8; 2] = [0xa5, 0x50];
+    const MAC: [u8; 6] = [0x00, 0x1c, 0x42, 0x0a, 0x0b, 0x0c];
+
+    /// Descriptor and data of a GetDeviceInfo answer
+    fn info_response() -> Vec<u8> {
+        let mut res = vec![0xa5, 0x5a, 0x14, 0x00, 0x00, 0x00, 0x04];
+        res.extend_from_slice(&[0x61, 0x1d, 0x01, 0x12]);
+        res.extend(0..16);
+        res
+    }
+
+    /// Descriptor and data of a GetLidarConf answer carrying the MAC address
+    fn mac_response() -> Vec<u8> {
+        let mut res = vec![0xa5, 0x5a, 0x0a, 0x00, 0x00, 0x00, 0x20];
+        res.extend_from_slice(&(MacAddr as u32).to_le_bytes());
+        res.extend_from_slice(&MAC);
+        res
+    }
+
+    #[test]
+    fn tcp_replays_answers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let device = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut req = [0u8; 2];
+            stream.read_exact(&mut req).unwrap();
+            assert_eq!(req, INFO_REQ);
+            // split mid-descriptor, as TCP segments may be
+            let res = info_response();
+            stream.write_all(&res[..4]).unwrap();
+            stream.flush().unwrap();
+            stream.write_all(&res[4..]).unwrap();
+
+            let mut req = vec![0u8; conf_req(MacAddr, None).unwrap().len()];
+            stream.read_exact(&mut req).unwrap();
+            assert_eq!(req, conf_req(MacAddr, None).unwrap());
+            stream.write_all(&mac_response()).unwrap();
+        });
+
+        let mut lidar = Lidar::connect_tcp(addr).unwrap();
+        let info = lidar.get_info().unwrap();
+        assert_eq!(info.model, 0x61);
+        assert_eq!(info.firmware_version, 0x011d);
+        assert_eq!(info.hardware_version, 0x12);
+        assert_eq!(info.serial_number, core::array::from_fn(|i| i as u8));
+        assert_eq!(lidar.get_mac_addr().unwrap().macaddr, MAC);
+        device.join().unwrap();
+    }
+
+    #[test]
+    fn udp_replays_answers() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let device = thread::spawn(move || {
+            let mut req = [0u8; 64];
+            let (len, peer) = socket.recv_from(&mut req).unwrap();
+            assert_eq!(req[..len], INFO_REQ);
+            // descriptor and data in one datagram, read in two parts
+            socket.send_to(&info_response(), peer).unwrap();
+
+            let (len, peer) = socket.recv_from(&mut req).unwrap();
+            assert_eq!(req[..len], conf_req(MacAddr, None).unwrap());
+            socket.send_to(&mac_response(), peer).unwrap();
+        });
+
+        let mut lidar = Lidar::connect_udp(addr).unwrap();
+        assert_eq!(lidar.get_info().unwrap().model, 0x61);
+        assert_eq!(lidar.get_mac_addr().unwrap().macaddr, MAC);
+        device.join().unwrap();
+    }
+}
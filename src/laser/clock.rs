@@ -0,0 +1,141 @@
+use crate::laser::protocol::Sample;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Source of the host timestamps put on samples
+pub trait Clock: Send + Sync {
+    /// Monotonic time elapsed since a fixed origin
+    fn now(&self) -> Duration;
+}
+
+/// Origin shared by every [`MonotonicClock`], so timestamps of different lidars compare
+static ORIGIN: OnceLock<Instant> = OnceLock::new();
+
+/// The default clock, backed by [`Instant`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MonotonicClock;
+
+impl MonotonicClock {
+    fn origin() -> Instant {
+        *ORIGIN.get_or_init(Instant::now)
+    }
+
+    /// Converts a timestamp of this clock back into an [`Instant`]
+    pub fn instant(timestamp: Duration) -> Instant {
+        Self::origin() + timestamp
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        Self::origin().elapsed()
+    }
+}
+
+/// Wall-clock time since the UNIX epoch, e.g. to timestamp recordings.
+///
+/// Unlike [`MonotonicClock`] it jumps when the system time is adjusted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+}
+
+/// A clock that only moves when told to, for tests and replays
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    nanos: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(start: Duration) -> ManualClock {
+        ManualClock {
+            nanos: AtomicU64::new(start.as_nanos() as u64),
+        }
+    }
+
+    pub fn set(&self, now: Duration) {
+        self.nanos.store(now.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.nanos.fetch_add(by.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+}
+
+/// Puts receive and measurement times on decoded samples
+pub(crate) struct Stamper {
+    clock: Arc<dyn Clock>,
+    /// time the lidar takes per measurement, zero if unknown
+    sample_duration: Duration,
+}
+
+impl Stamper {
+    pub(crate) fn new(clock: Arc<dyn Clock>, sample_duration: Duration) -> Stamper {
+        Stamper {
+            clock,
+            sample_duration,
+        }
+    }
+
+    pub(crate) fn sample_duration(&self) -> Duration {
+        self.sample_duration
+    }
+
+    pub(crate) fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    /// Stamps a sample received at `received`, `later` measurements before the newest one.
+    ///
+    /// The newest measurement of a read is assumed to have been taken as it arrived, earlier
+    /// ones are spaced back from it by the sample duration.
+    pub(crate) fn stamp(&self, sample: &mut Sample, received: Duration, later: usize) {
+        sample.received = received;
+        sample.timestamp = received.saturating_sub(self.sample_duration * later as u32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::laser::protocol::tests::node;
+
+    #[test]
+    fn back_dates_earlier_samples_of_a_read() {
+        let clock = Arc::new(ManualClock::new(Duration::from_secs(10)));
+        let stamper = Stamper::new(clock.clone(), Duration::from_micros(500));
+        clock.advance(Duration::from_millis(3));
+
+        let received = stamper.now();
+        let mut samples: Vec<Sample> = (0..4).map(|_| Sample::from_node(&node(false, 0, 0, 0)).unwrap()).collect();
+        for (i, sample) in samples.iter_mut().enumerate() {
+            stamper.stamp(sample, received, 3 - i);
+        }
+
+        let base = Duration::from_millis(10_003);
+        assert!(samples.iter().all(|s| s.received == base));
+        assert_eq!(samples[0].timestamp, base - Duration::from_micros(1500));
+        assert_eq!(samples[2].timestamp, base - Duration::from_micros(500));
+        assert_eq!(samples[3].timestamp, base);
+    }
+
+    #[test]
+    fn does_not_back_date_before_the_origin() {
+        let clock = Arc::new(ManualClock::new(Duration::from_millis(1)));
+        let stamper = Stamper::new(clock, Duration::from_micros(500));
+        let mut sample = Sample::from_node(&node(false, 0, 0, 0)).unwrap();
+        stamper.stamp(&mut sample, stamper.now(), 10);
+        assert_eq!(sample.timestamp, Duration::ZERO);
+    }
+}
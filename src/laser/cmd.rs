@@ -1,7 +1,10 @@
+use crate::error::RxError;
+
 // Commands
 // pub const DEFAULT_MOTOR_SPEED: u16 = 0xFFFF;
 // const SL_LIDAR_AUTOBAUD_MAGICBYTE: u8 = 0x41;
 
+#[allow(dead_code)]
 #[repr(u8)]
 pub enum SlLidarCmd {
     // Commands without payload and response
@@ -79,7 +82,8 @@ pub enum SlLidarCmd {
 //     param: u16,
 // }
 
-#[derive(Debug)]
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum SlLidarAnsType {
     DevInfo = 0x04,
@@ -99,12 +103,9 @@ pub enum SlLidarAnsType {
     AccBoardFlag = 0xFF,
 }
 
-// const SL_LIDAR_RESP_ACC_BOARD_FLAG_MOTOR_CTRL_SUPPORT_MASK: u8 = 0x1;
-//
-// struct SlLidarResponseAccBoardFlagT {
-//     support_flag: u32,
-// }
-//
+/// Set in the `GetAccBoardFlag` answer if the accessory board drives the motor by PWM
+pub(crate) const ACC_BOARD_FLAG_MOTOR_CTRL: u32 = 0x1;
+
 // enum SlLidarStatus {
 //     Ok = 0x0,
 //     Warning = 0x1,
@@ -119,9 +120,19 @@ pub enum SlLidarAnsType {
 // const SL_LIDAR_RESP_MEASUREMENT_CHECKBIT: u8 = 0x01;
 // const SL_LIDAR_RESP_MEASUREMENT_ANGLE_SHIFT: u8 = 0x01;
 
-pub(crate) struct SlLidarResponseSampleRateT {
-    pub(crate) std_sample_duration_us: u16,
-    pub(crate) express_sample_duration_us: u16,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlLidarResponseSampleRateT {
+    pub std_sample_duration_us: u16,
+    pub express_sample_duration_us: u16,
+}
+
+impl SlLidarResponseSampleRateT {
+    pub(crate) fn from_bytes(data: &[u8]) -> Self {
+        SlLidarResponseSampleRateT {
+            std_sample_duration_us: ((data[1] as u16) << 8) | data[0] as u16,
+            express_sample_duration_us: ((data[3] as u16) << 8) | data[2] as u16,
+        }
+    }
 }
 
 // struct SlLidarResponseMeasurementNodeT {
@@ -216,43 +227,114 @@ pub(crate) struct SlLidarResponseSampleRateT {
 // const SL_LIDAR_CONF_SCAN_COMMAND_SENSITIVITY: u8 = 5;
 //
 // const SL_LIDAR_CONF_ANGLE_RANGE: u8 = 0x00000000;
-// const SL_LIDAR_CONF_DESIRED_ROT_FREQ: u8 = 0x00000001;
 // const SL_LIDAR_CONF_SCAN_COMMAND_BITMAP: u8 = 0x00000002;
 // const SL_LIDAR_CONF_MIN_ROT_FREQ: u8 = 0x00000004;
 // const SL_LIDAR_CONF_MAX_ROT_FREQ: u8 = 0x00000005;
 // const SL_LIDAR_CONF_MAX_DISTANCE: u8 = 0x00000060;
-// const SL_LIDAR_CONF_LIDAR_MAC_ADDR: u32 = 0x00000079;
 
+/// Entries readable with `GetLidarConf`; those describing a scan mode take its id as payload
 #[repr(u32)]
 #[derive(Clone, Copy)]
-pub enum ScanModeConfEntry {
+pub enum ConfEntry {
+    DesiredRotFreq = 0x00000001,
     Count = 0x00000070,
     UsPerSample = 0x00000071,
     MaxDistance = 0x00000074,
     AnsType = 0x00000075,
     Typical = 0x0000007C,
     Name = 0x0000007F,
+    MacAddr = 0x00000079,
+    StaticIpAddr = 0x0001CCC0,
 }
 
 // const SL_LIDAR_CONF_MODEL_REVISION_ID: u32 = 0x00000080;
 // const SL_LIDAR_CONF_MODEL_NAME_ALIAS: u32 = 0x00000081;
 //
 // const SL_LIDAR_CONF_DETECTED_SERIAL_BPS: u32 = 0x000000A1;
-//
-// const SL_LIDAR_CONF_LIDAR_STATIC_IP_ADDR: u32 = 0x0001CCC0;
 // const SL_LIDAR_EXPRESS_SCAN_STABILITY_BITMAP: u8 = 4;
 // const SL_LIDAR_EXPRESS_SCAN_SENSITIVITY_BITMAP: u8 = 5;
 
-pub(crate) struct SlLidarResponseGetLidarConf {
+/// Writable device parameters for `SetLidarConf`
+#[derive(Debug, Clone, Copy)]
+pub enum LidarConf {
+    /// Desired motor rotation speed (in RPM)
+    DesiredRotFreq(u16),
+    /// Static IP configuration of network lidars
+    StaticIpAddr(SlLidarIpConfT),
+}
+
+impl LidarConf {
+    /// Configuration entry type as sent to the lidar
+    pub fn conf_type(&self) -> u32 {
+        match self {
+            LidarConf::DesiredRotFreq(_) => ConfEntry::DesiredRotFreq as u32,
+            LidarConf::StaticIpAddr(_) => ConfEntry::StaticIpAddr as u32,
+        }
+    }
+
+    /// Payload of the `SetLidarConf` request (type followed by value)
+    pub(crate) fn payload(&self) -> Vec<u8> {
+        let mut payload = self.conf_type().to_le_bytes().to_vec();
+        match self {
+            LidarConf::DesiredRotFreq(rpm) => payload.extend_from_slice(&rpm.to_le_bytes()),
+            LidarConf::StaticIpAddr(conf) => {
+                payload.extend_from_slice(&conf.ip_addr);
+                payload.extend_from_slice(&conf.net_mask);
+                payload.extend_from_slice(&conf.gw);
+            }
+        }
+        payload
+    }
+}
+
+pub struct SlLidarResponseGetLidarConf {
+    pub conf_type: u32,
+    pub payload: Vec<u8>,
+}
+
+impl SlLidarResponseGetLidarConf {
+    /// Fails unless the payload carries at least `len` bytes
+    pub(crate) fn expect_len(self, len: usize) -> Result<Self, RxError> {
+        if self.payload.len() < len {
+            return Err(RxError::UnexpectedResponse(SlLidarAnsType::GetLidarConf as u8));
+        }
+        Ok(self)
+    }
+
+    /// Payload read as a little endian `u16`, such as a scan mode count or id
+    pub(crate) fn payload_u16(&self) -> u16 {
+        u16::from_le_bytes(self.payload[..2].try_into().unwrap())
+    }
+
+    /// Payload read as a zero padded string, such as a scan mode name
+    pub(crate) fn payload_str(&self) -> String {
+        let end = self.payload.iter().position(|&b| b == 0).unwrap_or(self.payload.len());
+        String::from_utf8_lossy(&self.payload[..end]).into_owned()
+    }
+
+    pub(crate) fn from_bytes(data: &[u8]) -> Self {
+        SlLidarResponseGetLidarConf {
+            conf_type: u32::from_le_bytes(data[..4].try_into().unwrap()),
+            payload: data[4..].to_owned(),
+        }
+    }
+}
+
+pub(crate) struct SlLidarResponseSetLidarConf {
     pub(crate) conf_type: u32,
-    pub(crate) payload: Vec<u8>,
+    pub(crate) result: u32,
 }
 
-// struct SlLidarResponseSetLidarConf {
-//     _type: u32,
-//     result: u32,
-// }
+impl SlLidarResponseSetLidarConf {
+    pub(crate) fn from_bytes(data: &[u8]) -> Self {
+        SlLidarResponseSetLidarConf {
+            conf_type: u32::from_le_bytes(data[..4].try_into().unwrap()),
+            result: u32::from_le_bytes(data[4..8].try_into().unwrap()),
+        }
+    }
+}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct SlLidarResponseDeviceInfoT {
     pub model: u8,
     pub firmware_version: u16,
@@ -260,20 +342,91 @@ pub struct SlLidarResponseDeviceInfoT {
     pub serial_number: [u8; 16],
 }
 
+impl SlLidarResponseDeviceInfoT {
+    pub(crate) fn from_bytes(data: &[u8]) -> Self {
+        SlLidarResponseDeviceInfoT {
+            model: data[0],
+            firmware_version: ((data[2] as u16) << 8) | data[1] as u16,
+            hardware_version: data[3],
+            serial_number: data[4..20].try_into().unwrap(),
+        }
+    }
+
+    /// Name of the model family, from the upper nibble of `model`
+    pub fn model_name(&self) -> &'static str {
+        match self.model >> 4 {
+            0x1 => "RPLIDAR A1",
+            0x2 => "RPLIDAR A2",
+            0x3 => "RPLIDAR A3",
+            0x4 => "RPLIDAR C1",
+            0x6 => "RPLIDAR S1",
+            0x7 => "RPLIDAR S2",
+            0x8 => "RPLIDAR S3",
+            _ => "unknown",
+        }
+    }
+
+    /// Nominal range of the model family (metres), for lidars that don't report a scan mode's range
+    pub fn max_distance(&self) -> Option<f32> {
+        match self.model >> 4 {
+            0x1 | 0x4 => Some(12.0),
+            0x2 => Some(16.0),
+            0x3 => Some(25.0),
+            0x6 | 0x8 => Some(40.0),
+            0x7 => Some(30.0),
+            _ => None,
+        }
+    }
+
+    /// Serial number as printed on the device label
+    pub fn serial_number_hex(&self) -> String {
+        self.serial_number.iter().map(|b| format!("{:02X}", b)).collect()
+    }
+}
+
 pub struct SlLidarResponseDeviceHealthT {
     pub(crate) status: u8,
     pub(crate) error_code: u16,
 }
 
-// struct SlLidarIpConfT {
-//     ip_addr: [u8; 4],
-//     net_mask: [u8; 4],
-//     gw: [u8; 4],
-// }
-//
-// struct SlLidarResponseDeviceMacaddrInfoT {
-//     macaddr: [u8; 6],
-// }
+impl SlLidarResponseDeviceHealthT {
+    pub(crate) fn from_bytes(data: &[u8]) -> Self {
+        SlLidarResponseDeviceHealthT {
+            status: data[0],
+            error_code: ((data[2] as u16) << 8) | data[1] as u16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlLidarIpConfT {
+    pub ip_addr: [u8; 4],
+    pub net_mask: [u8; 4],
+    pub gw: [u8; 4],
+}
+
+impl SlLidarIpConfT {
+    pub(crate) fn from_bytes(data: &[u8]) -> Self {
+        SlLidarIpConfT {
+            ip_addr: data[0..4].try_into().unwrap(),
+            net_mask: data[4..8].try_into().unwrap(),
+            gw: data[8..12].try_into().unwrap(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlLidarResponseDeviceMacaddrInfoT {
+    pub macaddr: [u8; 6],
+}
+
+impl SlLidarResponseDeviceMacaddrInfoT {
+    pub(crate) fn from_bytes(data: &[u8]) -> Self {
+        SlLidarResponseDeviceMacaddrInfoT {
+            macaddr: data[..6].try_into().unwrap(),
+        }
+    }
+}
 
 // struct SlLidarResponseDesiredRotSpeedT {
 //     rpm: u16,
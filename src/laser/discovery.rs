@@ -0,0 +1,153 @@
+use crate::error::RxError;
+use crate::laser::cmd::SlLidarResponseDeviceInfoT;
+use crate::laser::lidar::Lidar;
+use serialport::{SerialPortInfo, SerialPortType, UsbPortInfo};
+
+/// USB-serial adapters (VID, PID) shipped with Slamtec lidars: Silicon Labs CP210x and WCH CH340
+pub const SLAMTEC_ADAPTERS: [(u16, u16); 2] = [(0x10c4, 0xea60), (0x1a86, 0x7523)];
+
+/// Baudrates tried when probing, most common first
+///
+/// A2M8/A3/S1 use 256000, A1/A2 115200, C1 460800 and S2/S3 1000000.
+pub const BAUDRATES: [u32; 4] = [256000, 115200, 460800, 1000000];
+
+/// A lidar that answered on a serial port
+#[derive(Debug, Clone)]
+pub struct DetectedLidar {
+    /// port name to pass to [`Lidar::open`]
+    pub port: String,
+    pub baudrate: u32,
+    /// the USB adapter the lidar is connected through
+    pub usb: UsbPortInfo,
+    pub info: SlLidarResponseDeviceInfoT,
+}
+
+impl DetectedLidar {
+    /// Connects to the detected lidar
+    pub fn open(&self) -> Result<Lidar, RxError> {
+        Ok(Lidar::open(&self.port, self.baudrate)?)
+    }
+}
+
+/// Lists serial ports behind a USB adapter used by Slamtec lidars
+pub fn candidate_ports() -> Result<Vec<(String, UsbPortInfo)>, RxError> {
+    Ok(slamtec_ports(serialport::available_ports()?))
+}
+
+/// Keeps the ports behind a USB adapter used by Slamtec lidars, in the given order
+pub fn slamtec_ports(ports: Vec<SerialPortInfo>) -> Vec<(String, UsbPortInfo)> {
+    ports
+        .into_iter()
+        .filter_map(|port| match port.port_type {
+            SerialPortType::UsbPort(usb) if SLAMTEC_ADAPTERS.contains(&(usb.vid, usb.pid)) => {
+                Some((port.port_name, usb))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Asks whatever is on `port` for its device info at the given baudrate
+pub fn probe(port: &str, baudrate: u32) -> Result<SlLidarResponseDeviceInfoT, RxError> {
+    let mut lidar = Lidar::open(port, baudrate)?;
+    // a lidar left scanning would bury the answer in measurements
+    lidar.stop(false)?;
+    lidar.get_info()
+}
+
+/// Finds the baudrate the lidar on `port` answers at, trying each of [`BAUDRATES`] in turn
+pub fn probe_baudrate(port: &str) -> Result<(u32, SlLidarResponseDeviceInfoT), RxError> {
+    first_answer(|baudrate| probe(port, baudrate))
+}
+
+/// Calls `probe` with each of [`BAUDRATES`] until it succeeds, failing with the last error
+fn first_answer<T>(mut probe: impl FnMut(u32) -> Result<T, RxError>) -> Result<(u32, T), RxError> {
+    let mut last_err = RxError::TimedOut;
+    for baudrate in BAUDRATES {
+        match probe(baudrate) {
+            Ok(answer) => return Ok((baudrate, answer)),
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+/// Finds lidars on all candidate ports, trying each of [`BAUDRATES`] until one answers
+pub fn discover() -> Result<Vec<DetectedLidar>, RxError> {
+    let mut found = Vec::new();
+
+    for (port, usb) in candidate_ports()? {
+        if let Ok((baudrate, info)) = probe_baudrate(&port) {
+            found.push(DetectedLidar {
+                port,
+                baudrate,
+                usb,
+                info,
+            });
+        }
+    }
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port(name: &str, port_type: SerialPortType) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: name.to_string(),
+            port_type,
+        }
+    }
+
+    fn usb(vid: u16, pid: u16) -> SerialPortType {
+        SerialPortType::UsbPort(UsbPortInfo {
+            vid,
+            pid,
+            serial_number: None,
+            manufacturer: None,
+            product: None,
+        })
+    }
+
+    #[test]
+    fn keeps_ports_on_slamtec_adapters() {
+        let ports = vec![
+            port("/dev/ttyS0", SerialPortType::Unknown),
+            port("/dev/ttyUSB0", usb(0x10c4, 0xea60)),
+            port("/dev/ttyACM0", usb(0x2341, 0x0043)),
+            port("/dev/ttyUSB1", usb(0x1a86, 0x7523)),
+            // same vendor, another product
+            port("/dev/ttyUSB2", usb(0x10c4, 0xea70)),
+            port("/dev/rfcomm0", SerialPortType::BluetoothPort),
+        ];
+
+        let names: Vec<String> = slamtec_ports(ports).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["/dev/ttyUSB0", "/dev/ttyUSB1"]);
+    }
+
+    #[test]
+    fn probes_baudrates_most_common_first() {
+        let mut tried = Vec::new();
+        let found = first_answer(|baudrate| {
+            tried.push(baudrate);
+            if baudrate == 460800 { Ok("C1") } else { Err(RxError::TimedOut) }
+        });
+
+        assert!(matches!(found, Ok((460800, "C1"))));
+        assert_eq!(tried, [256000, 115200, 460800]);
+    }
+
+    #[test]
+    fn fails_with_the_last_probe_error() {
+        let mut tried = Vec::new();
+        let found: Result<(u32, ()), _> = first_answer(|baudrate| {
+            tried.push(baudrate);
+            Err(RxError::UnexpectedResponse(baudrate as u8))
+        });
+
+        assert!(matches!(found, Err(RxError::UnexpectedResponse(0x40))));
+        assert_eq!(tried, BAUDRATES);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::laser::protocol;
+use crate::laser::protocol::{DenseSample, Sample};
+
+/// Packet layout of a measurement stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Framing {
+    /// 5 byte nodes of a standard scan
+    Node,
+    /// 84 byte capsules of a dense express scan
+    DenseCapsule,
+}
+
+impl Framing {
+    /// Size of one packet in bytes
+    pub(crate) const fn len(self) -> usize {
+        match self {
+            Framing::Node => 5,
+            Framing::DenseCapsule => 84,
+        }
+    }
+
+    /// Consecutive valid packets required before trusting an alignment.
+    ///
+    /// Nodes only carry three check bits, so a single match is likely to be a coincidence.
+    const fn lock_len(self) -> usize {
+        match self {
+            Framing::Node => 4,
+            Framing::DenseCapsule => 1,
+        }
+    }
+
+    /// Checks the sync/check bits (and checksum) of a packet
+    pub(crate) fn is_valid(self, packet: &[u8]) -> bool {
+        match self {
+            Framing::Node => {
+                let s = packet[0] & 0b11;
+                let angle_q6 = ((packet[2] as u16) << 7) | (packet[1] as u16 >> 1);
+                (s == 0b01 || s == 0b10) && packet[1] & 0b01 == 1 && angle_q6 < 360 << 6
+            }
+            Framing::DenseCapsule => {
+                packet[0] >> 4 == 0xa
+                    && packet[1] >> 4 == 0x5
+                    && protocol::checksum(&packet[2..]) == (packet[1] << 4) | (packet[0] & 0b1111)
+            }
+        }
+    }
+}
+
+/// Splits a raw measurement byte stream into packets.
+///
+/// While aligned, packets are consumed whole. A packet failing its checks drops the framer back
+/// into searching, where it advances one byte at a time until enough consecutive valid packets
+/// line up again. Skipped bytes are counted in `discarded`.
+pub(crate) struct Framer {
+    framing: Framing,
+    buffer: Vec<u8>,
+    /// start of unconsumed data in `buffer`
+    pos: usize,
+    aligned: bool,
+    /// bytes skipped while searching
+    pub(crate) discarded: u64,
+    /// times alignment was lost
+    pub(crate) resyncs: u64,
+}
+
+impl Framer {
+    pub(crate) fn new(framing: Framing) -> Framer {
+        Framer {
+            framing,
+            buffer: Vec::new(),
+            pos: 0,
+            aligned: false,
+            discarded: 0,
+            resyncs: 0,
+        }
+    }
+
+    /// Appends received bytes
+    pub(crate) fn push(&mut self, bytes: &[u8]) {
+        self.buffer.drain(..self.pos);
+        self.pos = 0;
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Number of whole packets buffered but not yet returned
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub(crate) fn buffered(&self) -> usize {
+        (self.buffer.len() - self.pos) / self.framing.len()
+    }
+
+    /// Returns the next valid packet, or `None` if more data is needed
+    pub(crate) fn next_packet(&mut self) -> Option<&[u8]> {
+        let len = self.framing.len();
+
+        loop {
+            let available = &self.buffer[self.pos..];
+
+            if !self.aligned {
+                let lock = len * self.framing.lock_len();
+                if available.len() < lock {
+                    return None;
+                }
+                if available[..lock].chunks(len).all(|p| self.framing.is_valid(p)) {
+                    self.aligned = true;
+                } else {
+                    self.pos += 1;
+                    self.discarded += 1;
+                    continue;
+                }
+            }
+
+            if available.len() < len {
+                return None;
+            }
+            if self.framing.is_valid(&available[..len]) {
+                self.pos += len;
+                return Some(&self.buffer[self.pos - len..self.pos]);
+            }
+
+            self.aligned = false;
+            self.resyncs += 1;
+        }
+    }
+}
+
+/// Turns packets into samples, keeping the state needed across packets
+pub(crate) struct Decoder {
+    framing: Framing,
+    /// dense capsules are only decoded once the next one's start angle is known
+    capsule: Option<DenseSample>,
+    last_angle: u16,
+}
+
+impl Decoder {
+    pub(crate) fn new(framing: Framing) -> Decoder {
+        Decoder {
+            framing,
+            capsule: None,
+            last_angle: 0,
+        }
+    }
+
+    /// Number of measurements received but not yet decoded
+    pub(crate) fn pending(&self) -> usize {
+        self.capsule.as_ref().map_or(0, |capsule| capsule.cabin.len())
+    }
+
+    /// Decodes a valid packet, appending its samples to `out`
+    pub(crate) fn decode(&mut self, packet: &[u8], out: &mut Vec<Sample>) {
+        match self.framing {
+            Framing::Node => out.extend(Sample::from_node(packet)),
+            Framing::DenseCapsule => {
+                let capsule = DenseSample::from_capsule(packet);
+                let restarted = capsule.start;
+                let next_angle = capsule.angle;
+
+                if let Some(prev) = self.capsule.replace(capsule) {
+                    if restarted {
+                        return;
+                    }
+                    for mut sample in prev.samples(next_angle) {
+                        // a revolution starts where the angle wraps around
+                        sample.start = sample.angle_q6 < self.last_angle;
+                        self.last_angle = sample.angle_q6;
+                        out.push(sample);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::laser::protocol::tests::{capsule, node};
+
+    fn nodes(count: u16) -> Vec<u8> {
+        (0..count).flat_map(|i| node(i == 0, 15, i * 64, 4000 + i)).collect()
+    }
+
+    fn drain(framer: &mut Framer) -> Vec<Vec<u8>> {
+        let mut packets = Vec::new();
+        while let Some(packet) = framer.next_packet() {
+            packets.push(packet.to_vec());
+        }
+        packets
+    }
+
+    #[test]
+    fn splits_aligned_nodes() {
+        let stream = nodes(6);
+        let mut framer = Framer::new(Framing::Node);
+        // packets may arrive split across reads
+        framer.push(&stream[..7]);
+        assert!(drain(&mut framer).is_empty());
+        framer.push(&stream[7..]);
+
+        assert_eq!(drain(&mut framer), stream.chunks(5).map(<[u8]>::to_vec).collect::<Vec<_>>());
+        assert_eq!((framer.discarded, framer.resyncs), (0, 0));
+    }
+
+    #[test]
+    fn resyncs_after_garbage() {
+        let stream = nodes(10);
+        let mut framer = Framer::new(Framing::Node);
+        // a node cut short, then line noise
+        framer.push(&stream[2..5]);
+        framer.push(&stream[5..25]);
+        framer.push(&[0x00, 0x00]);
+        framer.push(&stream[25..]);
+
+        let packets = drain(&mut framer);
+        assert_eq!(packets.len(), 9);
+        assert_eq!(packets[0], stream[5..10]);
+        assert_eq!(packets[8], stream[45..]);
+        assert_eq!(framer.discarded, 3 + 2);
+        assert_eq!(framer.resyncs, 1);
+    }
+
+    #[test]
+    fn decodes_dense_capsules_once_the_next_arrives() {
+        let mut framer = Framer::new(Framing::DenseCapsule);
+        let mut decoder = Decoder::new(Framing::DenseCapsule);
+        let mut samples = Vec::new();
+
+        // 2 degree capsules, wrapping around after the second
+        for (start, angle) in [(true, 356), (false, 358), (false, 0)] {
+            framer.push(&[0xff]);
+            framer.push(&capsule(start, angle * 64, [500; 40]));
+            while let Some(packet) = framer.next_packet() {
+                decoder.decode(packet, &mut samples);
+            }
+        }
+
+        assert_eq!(samples.len(), 80);
+        assert_eq!(decoder.pending(), 40);
+        // each capsule spreads over the 128 q6 steps to the next one
+        assert_eq!(samples[0].angle_q6, 356 * 64);
+        assert_eq!(samples[39].angle_q6, 356 * 64 + 128 * 39 / 40);
+        assert_eq!(samples[40].angle_q6, 358 * 64);
+        assert_eq!(samples[79].angle_q6, 358 * 64 + 128 * 39 / 40);
+        assert_eq!(framer.discarded, 3);
+    }
+}
@@ -1,74 +1,156 @@
 use crate::error::RxError;
-use crate::laser::cmd::ScanModeConfEntry::*;
-use crate::laser::cmd::SlLidarCmd::{ExpressScan, GetDeviceHealth, GetDeviceInfo, GetLidarConf, GetSampleRate, HQMotorSpeedCtrl, Reset, Scan, Stop};
+use crate::laser::cmd::ConfEntry::*;
+use crate::laser::cmd::SlLidarAnsType::{AccBoardFlag, DevHealth, DevInfo, SampleRate};
+use crate::laser::cmd::SlLidarCmd::{
+    ExpressScan, GetAccBoardFlag, GetDeviceHealth, GetDeviceInfo, GetSampleRate, HQMotorSpeedCtrl, Reset, Scan,
+    SetLidarConf, SetMotorPWM, Stop,
+};
 use crate::laser::cmd::{
-    ScanModeConfEntry, SlLidarResponseDeviceHealthT, SlLidarResponseDeviceInfoT,
-    SlLidarResponseGetLidarConf, SlLidarResponseSampleRateT,
+    LidarConf, ACC_BOARD_FLAG_MOTOR_CTRL, ConfEntry, SlLidarAnsType, SlLidarIpConfT, SlLidarResponseDeviceHealthT,
+    SlLidarResponseDeviceInfoT, SlLidarResponseDeviceMacaddrInfoT, SlLidarResponseGetLidarConf,
+    SlLidarResponseSampleRateT,
 };
-use crate::laser::protocol::{Response, ResponseDescriptor, Sample};
-use serialport::SerialPort;
+use crate::laser::buffer;
+use crate::laser::buffer::BufferConfig;
+use crate::laser::channel::{Channel, TcpChannel, UdpChannel};
+use crate::laser::clock::{Clock, MonotonicClock, Stamper};
+use crate::laser::framer::Framing;
+use crate::laser::protocol;
+use crate::laser::protocol::{Response, ResponseDescriptor};
+use crate::laser::session;
+use crate::laser::session::{ScanControl, ScanSession, ScanState, STOP_WAIT};
+use std::io;
 use std::io::{Read, Write};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::{mpsc, Arc};
-use std::thread;
-use std::thread::{sleep, JoinHandle};
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
 use std::time::Duration;
 
-const S1_BAUD: usize = 256000;
+pub(crate) const S1_BAUD: usize = 256000;
+
+/// How a lidar's motor is switched off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MotorControl {
+    /// S and C series: `HQMotorSpeedCtrl`, in RPM
+    Rpm,
+    /// A series on an accessory board with motor control: `SetMotorPWM`
+    Pwm,
+    /// A series on a plain USB adapter: the motor is wired to DTR
+    Dtr,
+}
 
-/// Represents a serial connection to a lidar
-pub struct Lidar {
-    /// serial connection object
-    transport: Box<dyn SerialPort>,
+/// Which scan a lidar runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanMode {
+    /// [`Lidar::start_scan`], one measurement per packet
+    Standard,
+    /// [`Lidar::start_scan_dense`], 40 measurements per capsule
+    Dense,
+}
+
+impl ScanMode {
+    /// Whether `name`, from the lidar's scan mode table, is this mode
+    pub(crate) fn matches(self, name: &str) -> bool {
+        match self {
+            ScanMode::Standard => name == "Standard",
+            ScanMode::Dense => name == "DenseBoost",
+        }
+    }
+}
+
+/// How long a request may wait for the lidar to answer
+const TIMEOUT: Duration = Duration::from_millis(1000);
 
-    /// reader thread handle
-    thread_handle: Option<JoinHandle<()>>,
-    /// Should nuke `reader_thread`?
-    nuke: Arc<AtomicBool>,
+/// Represents a connection to a lidar
+pub struct Lidar {
+    /// serial or network connection object
+    transport: Box<dyn Channel>,
+
+    /// state of the most recent scan
+    control: Arc<ScanControl>,
+    /// a reader thread may have shortened the (possibly shared) read timeout
+    restore_timeout: bool,
+    /// buffering between reader thread and consumer
+    buffer: BufferConfig,
+    /// source of sample timestamps
+    clock: Arc<dyn Clock>,
+    /// sample durations, queried once before the first scan
+    sample_rate: Option<SlLidarResponseSampleRateT>,
+    /// how to stop the motor, known once a scan of this connection spun it up
+    motor: Option<MotorControl>,
 }
 
 impl Lidar {
     /// initializes a serial connection to the lidar on the given port.
     pub fn init(port: String) -> Result<Lidar, serialport::Error> {
-        serialport::new(&port, S1_BAUD as u32)
-            .timeout(Duration::from_millis(1000))
+        Self::open(&port, S1_BAUD as u32)
+    }
+
+    /// initializes a serial connection to the lidar at a specific baudrate.
+    pub fn open(port: &str, baudrate: u32) -> Result<Lidar, serialport::Error> {
+        serialport::new(port, baudrate)
+            .timeout(TIMEOUT)
             .open()
-            .map(|transport| Lidar {
-                nuke: Arc::new(AtomicBool::new(false)),
-                transport,
-                thread_handle: None,
-            })
+            .map(|transport| Self::with_channel(Box::new(transport)))
+    }
+
+    /// initializes a TCP connection to a network lidar.
+    pub fn connect_tcp(addr: impl ToSocketAddrs) -> Result<Lidar, RxError> {
+        let channel = TcpChannel::connect(addr, TIMEOUT)?;
+        Ok(Self::with_channel(Box::new(channel)))
     }
 
-    /// Generates the checksum for a given message
-    fn checksum(payload: &[u8]) -> u8 {
-        payload.iter().fold(0, |acc, x| acc ^ x)
+    /// initializes a UDP connection to a network lidar.
+    pub fn connect_udp(addr: impl ToSocketAddrs) -> Result<Lidar, RxError> {
+        let channel = UdpChannel::connect(addr, TIMEOUT)?;
+        Ok(Self::with_channel(Box::new(channel)))
+    }
+
+    /// Drives a lidar over an already established channel
+    pub fn with_channel(transport: Box<dyn Channel>) -> Lidar {
+        Lidar {
+            transport,
+            control: Arc::new(ScanControl::new(None)),
+            restore_timeout: false,
+            buffer: BufferConfig::default(),
+            clock: Arc::new(MonotonicClock),
+            sample_rate: None,
+            motor: None,
+        }
+    }
+
+    /// Configures the sample buffer used by subsequent scans
+    pub fn set_buffer(&mut self, config: BufferConfig) {
+        self.buffer = config;
+    }
+
+    /// Replaces the clock timestamping samples of subsequent scans
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// State of the most recently started scan
+    pub fn scan_state(&self) -> ScanState {
+        self.control.state()
     }
 
     /// Performs a request with a single response
     fn single_req(&mut self, req: &[u8]) -> Result<Response, RxError> {
-        self.transport.write_all(&req)?;
+        // answers would be interleaved with scan data
+        if self.control.state() == ScanState::Scanning {
+            return Err(RxError::ScanInProgress);
+        }
+        if self.restore_timeout {
+            self.transport.set_timeout(TIMEOUT)?;
+            self.restore_timeout = false;
+        }
+
+        self.transport.write_all(req)?;
         // response header
         let mut descriptor_bytes = [0u8; 7];
 
         self.transport.read_exact(&mut descriptor_bytes)?;
 
-        if descriptor_bytes[0..2] != [0xa5, 0x5a] {
-            return Err(RxError::Corrupted(descriptor_bytes));
-        }
-
-        let send_mode = (descriptor_bytes[5] & 0b11000000) >> 6;
-        let data_type = descriptor_bytes[6];
-
-        descriptor_bytes[5] = descriptor_bytes[5] ^ (descriptor_bytes[5] & 0b11000000);
-        let len = crate::util::read_le_u32(&mut &descriptor_bytes[2..6]);
-
-        let descriptor = ResponseDescriptor {
-            len,
-            send_mode,
-            data_type,
-        };
+        let descriptor = ResponseDescriptor::parse(descriptor_bytes)?;
 
         // data
         let mut data = vec![0u8; descriptor.len as usize];
@@ -78,289 +160,305 @@ impl Lidar {
     }
 
     /// stops the lidar
-    pub fn stop(&mut self, reset: bool) {
-        self.nuke.store(true, Ordering::Relaxed);
-        self.transport
-            .write_all(&[0xa5, (if reset { Reset } else { Stop }) as u8])
-            .unwrap();
+    ///
+    /// Sends Stop (or Reset) and waits for in-flight data to drain until the port is quiet,
+    /// ending any running scan session. Afterwards the lidar is ready for another scan.
+    pub fn stop(&mut self, reset: bool) -> Result<(), RxError> {
+        let scanning = self.control.state() == ScanState::Scanning;
+        if scanning {
+            self.control.nuke();
+        }
+
+        let sent = self
+            .transport
+            .write_all(&[0xa5, (if reset { Reset } else { Stop }) as u8]);
+
+        // the reader thread drains the port itself before exiting
+        let stopped = if scanning {
+            self.control.wait_stopped(Some(STOP_WAIT))
+        } else {
+            session::drain(self.transport.as_mut())
+        };
+
+        self.transport.set_timeout(TIMEOUT)?;
+        self.restore_timeout = false;
+        sent?;
+        stopped
     }
 
     /// Resets/reboots the lidar
-    pub fn reset(&mut self) {
-        self.stop(true);
+    pub fn reset(&mut self) -> Result<(), RxError> {
+        self.stop(true)
     }
 
-    /// Sets motor speed
-    ///
-    /// _Unsupported on RPLIDAR S1?_
-    ///
-    /// TODO check this
-    fn set_motor_speed(&mut self, speed: u16) {
-        let speed_bytes = speed.to_le_bytes();
-        let mut req = [
-            0xa5,
-            HQMotorSpeedCtrl as u8,
-            0x02,
-            speed_bytes[0],
-            speed_bytes[1],
-            0,
-        ];
-        req[5] = Self::checksum(&req);
-        self.transport.write_all(&req).unwrap()
+    /// Works out how the motor of this model is controlled
+    fn motor_control(&mut self) -> Result<MotorControl, RxError> {
+        // A1, A2 and A3; newer series take a speed in RPM
+        if !matches!(self.get_info()?.model >> 4, 1..=3) {
+            return Ok(MotorControl::Rpm);
+        }
+
+        let req = protocol::payload_req(GetAccBoardFlag, &0u32.to_le_bytes());
+        let flags = match self.single_req(&req).and_then(|res| res.expect(AccBoardFlag, 4)) {
+            Ok(res) => u32::from_le_bytes(res.data[..4].try_into().unwrap()),
+            // lidars without an accessory board may not answer at all
+            Err(RxError::PortError(err)) if err.kind == serialport::ErrorKind::Io(io::ErrorKind::TimedOut) => 0,
+            Err(err) => return Err(err),
+        };
+
+        Ok(if flags & ACC_BOARD_FLAG_MOTOR_CTRL != 0 {
+            MotorControl::Pwm
+        } else {
+            MotorControl::Dtr
+        })
     }
 
-    /// Retrieves device information
-    pub fn get_info(&mut self) -> SlLidarResponseDeviceInfoT {
-        let res = self
-            .single_req(&[0xa5, GetDeviceInfo as u8])
-            .expect("Could not read device info");
-        let data = res.data;
-
-        SlLidarResponseDeviceInfoT {
-            model: data[0],
-            firmware_version: ((data[2] as u16) << 8) | data[1] as u16,
-            hardware_version: data[3],
-            serial_number: data[4..20].try_into().unwrap(),
+    /// Stops the motor, the way the model supports
+    fn stop_motor(&mut self, motor: MotorControl) -> Result<(), RxError> {
+        match motor {
+            MotorControl::Rpm => self.transport.write_all(&protocol::payload_req(HQMotorSpeedCtrl, &0u16.to_le_bytes()))?,
+            MotorControl::Pwm => self.transport.write_all(&protocol::payload_req(SetMotorPWM, &0u16.to_le_bytes()))?,
+            // DTR high stops the motor
+            MotorControl::Dtr => self.transport.set_dtr(true)?,
         }
+        Ok(())
+    }
+
+    /// Retrieves device information
+    pub fn get_info(&mut self) -> Result<SlLidarResponseDeviceInfoT, RxError> {
+        let res = self.single_req(&[0xa5, GetDeviceInfo as u8])?.expect(DevInfo, 20)?;
+
+        Ok(SlLidarResponseDeviceInfoT::from_bytes(&res.data))
     }
 
     /// Retrieves the lidar's health
-    pub fn get_health(&mut self) -> SlLidarResponseDeviceHealthT {
-        let res = self
-            .single_req(&[0xa5, GetDeviceHealth as u8])
-            .expect("Could not read device health");
-        let data = res.data;
-
-        SlLidarResponseDeviceHealthT {
-            status: data[0],
-            error_code: ((data[2] as u16) << 8) | data[1] as u16,
-        }
+    pub fn get_health(&mut self) -> Result<SlLidarResponseDeviceHealthT, RxError> {
+        let res = self.single_req(&[0xa5, GetDeviceHealth as u8])?.expect(DevHealth, 3)?;
+
+        Ok(SlLidarResponseDeviceHealthT::from_bytes(&res.data))
     }
 
-    pub fn get_health_str(&mut self) -> &'static str {
-        ["healthy", "warning", "error"]
-            .get(self.get_health().status as usize)
-            .unwrap()
+    pub fn get_health_str(&mut self) -> Result<&'static str, RxError> {
+        Ok(["healthy", "warning", "error"]
+            .get(self.get_health()?.status as usize)
+            .unwrap_or(&"unknown"))
     }
 
     /// Returns the sampling rate of the lidar
-    pub fn get_sample_rate(&mut self) -> SlLidarResponseSampleRateT {
-        let res = self
-            .single_req(&[0xa5, GetSampleRate as u8])
-            .expect("Could not read sample rate");
-        let data = res.data;
-
-        SlLidarResponseSampleRateT {
-            std_sample_duration_us: ((data[1] as u16) << 8) | data[0] as u16,
-            express_sample_duration_us: ((data[3] as u16) << 8) | data[2] as u16,
-        }
+    pub fn get_sample_rate(&mut self) -> Result<SlLidarResponseSampleRateT, RxError> {
+        let res = self.single_req(&[0xa5, GetSampleRate as u8])?.expect(SampleRate, 4)?;
+        let rate = SlLidarResponseSampleRateT::from_bytes(&res.data);
+        self.sample_rate = Some(rate);
+
+        Ok(rate)
     }
 
     /// Queries the lidar for specific configuration settings
     pub fn get_lidar_conf(
         &mut self,
-        entry: ScanModeConfEntry,
+        entry: ConfEntry,
         payload: Option<u16>,
-    ) -> SlLidarResponseGetLidarConf {
-        let mut req = [0u8; 12];
+    ) -> Result<SlLidarResponseGetLidarConf, RxError> {
+        let res = self.single_req(&protocol::conf_req(entry, payload)?)?.expect(SlLidarAnsType::GetLidarConf, 4)?;
 
-        req[0] = 0xa5;
-        req[1] = GetLidarConf as u8;
-        req[3..7].copy_from_slice((entry as u32).to_le_bytes().as_ref());
+        Ok(SlLidarResponseGetLidarConf::from_bytes(&res.data))
+    }
 
-        match entry {
-            Count | Typical => {
-                req[2] = 4;
-                req[7] = Self::checksum(&req[..7]);
-            }
-            _ => {
-                req[2] = 8;
-                req[7..9].copy_from_slice(payload.unwrap().to_le_bytes().as_ref());
-                req[11] = Self::checksum(&req[..11]);
-            }
-        }
+    /// Returns the number of entries in the lidar's scan mode table
+    pub fn get_scan_mode_count(&mut self) -> Result<u16, RxError> {
+        let conf = self.get_lidar_conf(Count, None)?.expect_len(2)?;
+        Ok(conf.payload_u16())
+    }
 
-        let res = self
-            .single_req(
-                &req[..(match entry {
-                    Count | Typical => 8,
-                    _ => 12,
-                })],
-            )
-            .expect("Could not read lidar conf");
-        let data = res.data;
-
-        SlLidarResponseGetLidarConf {
-            conf_type: u32::from_le_bytes(data[..4].try_into().unwrap()),
-            payload: data[4..].to_owned(),
-        }
+    /// Returns the id of the mode the lidar recommends
+    pub fn get_typical_scan_mode(&mut self) -> Result<u16, RxError> {
+        let conf = self.get_lidar_conf(Typical, None)?.expect_len(2)?;
+        Ok(conf.payload_u16())
     }
 
-    /// Waits for the reader thread to exit.
-    pub fn join(&mut self) {
-        if let Some(handle) = self.thread_handle.take() {
-            handle.join().unwrap();
-        }
+    /// Returns the name of the given scan mode
+    pub fn get_scan_mode_name(&mut self, mode: u16) -> Result<String, RxError> {
+        Ok(self.get_lidar_conf(Name, Some(mode))?.payload_str())
     }
 
-    /// Requests transmission of laser data from the lidar
-    pub fn start_scan(&mut self) -> Result<Receiver<Sample>, serialport::Error> {
-        // signal lidar to begin a scan
-        self.transport.write_all(&[0xa5, Scan as u8])?;
+    /// Looks up the id of a scan mode in the lidar's mode table, by name.
+    ///
+    /// A dense scan falls back to the typical mode if no mode is named like it, as lidars
+    /// streaming dense capsules recommend them.
+    pub fn scan_mode_id(&mut self, mode: ScanMode) -> Result<u16, RxError> {
+        for id in 0..self.get_scan_mode_count()? {
+            if mode.matches(&self.get_scan_mode_name(id)?) {
+                return Ok(id);
+            }
+        }
 
-        let nuke = Arc::clone(&self.nuke);
-        let (tx, rx) = mpsc::channel();
-        let transport = self
-            .transport
-            .try_clone()
-            .expect("Serial port channels can be cloned.");
+        match mode {
+            ScanMode::Standard => Err(RxError::UnknownScanMode),
+            ScanMode::Dense => self.get_typical_scan_mode(),
+        }
+    }
 
-        // start reader thread
-        self.thread_handle = Some(thread::spawn(move || {
-            Self::reader_thread(tx, transport, nuke);
-        }));
+    /// Returns how long one measurement takes in the given scan mode
+    pub fn get_us_per_sample(&mut self, mode: u16) -> Result<Duration, RxError> {
+        let conf = self.get_lidar_conf(UsPerSample, Some(mode))?.expect_len(4)?;
+        let us_q8 = u32::from_le_bytes(conf.payload[..4].try_into().unwrap());
 
-        Ok(rx)
+        Ok(Duration::from_nanos(us_q8 as u64 * 1000 / (1 << 8)))
     }
 
-    /// Thread that receives scan data
-    fn reader_thread(tx: Sender<Sample>, mut transport: Box<dyn SerialPort>, nuke: Arc<AtomicBool>) {
-        let mut seeking = true;
-        let mut descriptor = [0u8; 7];
+    /// Returns the maximum range of the given scan mode in metres
+    pub fn get_max_distance(&mut self, mode: u16) -> Result<f32, RxError> {
+        let conf = self.get_lidar_conf(MaxDistance, Some(mode))?.expect_len(4)?;
+        let m_q8 = u32::from_le_bytes(conf.payload[..4].try_into().unwrap());
 
-        transport
-            .read_exact(&mut descriptor)
-            .expect("missing descriptor");
+        Ok(m_q8 as f32 / (1 << 8) as f32)
+    }
 
-        if descriptor != [0xa5, 0x5a, 0x05, 0x00, 0x00, 0x40, 0x81] {
-            eprintln!("Unable to read lidar stream (malformed descriptor)");
-            return;
-        }
+    /// Writes a configuration entry to the lidar
+    pub fn set_lidar_conf(&mut self, conf: LidarConf) -> Result<(), RxError> {
+        let res = self.single_req(&protocol::payload_req(SetLidarConf, &conf.payload()))?;
+        protocol::check_set_conf(&conf, &res)
+    }
 
-        // give the lidar time to spin up
-        sleep(Duration::from_millis(1000));
+    /// Retrieves the MAC address of a network lidar
+    pub fn get_mac_addr(&mut self) -> Result<SlLidarResponseDeviceMacaddrInfoT, RxError> {
+        let conf = self.get_lidar_conf(MacAddr, None)?.expect_len(6)?;
+        Ok(SlLidarResponseDeviceMacaddrInfoT::from_bytes(&conf.payload))
+    }
 
-        loop {
-            const BATCH: usize = S1_BAUD / 500;
-            let mut data = [0u8; 5 * BATCH];
+    /// Retrieves the static IP configuration of a network lidar
+    pub fn get_ip_conf(&mut self) -> Result<SlLidarIpConfT, RxError> {
+        let conf = self.get_lidar_conf(StaticIpAddr, None)?.expect_len(12)?;
+        Ok(SlLidarIpConfT::from_bytes(&conf.payload))
+    }
 
-            if let Err(err) = transport.read_exact(&mut data) {
-                if nuke.load(Ordering::Relaxed) {
-                    nuke.store(false, Ordering::Relaxed);
-                    println!("Scan stopped.");
-                    return;
-                }
-                panic!("Unable to read lidar stream (malformed data): {}", err);
-            }
+    /// Changes the static IP configuration of a network lidar
+    ///
+    /// The new address takes effect after the lidar is rebooted.
+    pub fn set_ip_conf(&mut self, conf: SlLidarIpConfT) -> Result<(), RxError> {
+        self.set_lidar_conf(LidarConf::StaticIpAddr(conf))
+    }
 
-            for i in 0..BATCH {
-                let slice = &data[i * 5..i * 5 + 5];
-
-                // checks
-                let s = slice[0] & 0b11;
-                if s == 0b11 || s == 0b00 || slice[1] & 0b01 != 1 {
-                    eprintln!("parity failed: {:x?}", slice);
-                    continue;
-                }
-
-                let sample = Sample {
-                    start: (slice[0] & 1) != 0,
-                    intensity: slice[0] >> 2,
-                    angle: ((slice[2] as u16) << 1) | (slice[1] as u16 >> 7),
-                    distance: (((slice[4] as u16) << 8) | slice[3] as u16) / 4,
-                };
-
-                if seeking && !sample.start {
-                    continue;
-                }
-
-                seeking = false;
-                tx.send(sample).unwrap();
-            }
-        }
+    /// Waits for the reader thread to exit.
+    pub fn join(&mut self) {
+        let _ = self.control.wait_stopped(None);
     }
 
     /// Requests transmission of laser data from the lidar
-    pub fn start_scan_dense(&mut self) -> Result<Receiver<Sample>, serialport::Error> {
-        // signal lidar to begin a scan
-        self.transport.write_all(&[0xa5, ExpressScan as u8, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x22])?;
+    ///
+    /// Fails with [`RxError::ScanInProgress`] while a previous scan is still running.
+    pub fn start_scan(&mut self) -> Result<ScanSession, RxError> {
+        self.start_scan_mode(ScanMode::Standard)
+    }
 
-        let nuke = Arc::clone(&self.nuke);
-        let (tx, rx) = mpsc::channel();
-        let transport = self
-            .transport
-            .try_clone()
-            .expect("Serial port channels can be cloned.");
+    /// Requests transmission of laser data from the lidar
+    pub fn start_scan_dense(&mut self) -> Result<ScanSession, RxError> {
+        self.start_scan_mode(ScanMode::Dense)
+    }
 
-        // start reader thread
-        self.thread_handle = Some(thread::spawn(move || {
-            Self::reader_thread_dense(tx, transport, nuke);
-        }));
+    /// Sends the scan request of the given mode and starts a reader thread decoding the answer
+    pub fn start_scan_mode(&mut self, mode: ScanMode) -> Result<ScanSession, RxError> {
+        if self.control.state() == ScanState::Scanning {
+            return Err(RxError::ScanInProgress);
+        }
 
-        Ok(rx)
-    }
+        let (req, framing): (&[u8], _) = match mode {
+            ScanMode::Standard => (&[0xa5, Scan as u8], Framing::Node),
+            ScanMode::Dense => (
+                &[0xa5, ExpressScan as u8, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x22],
+                Framing::DenseCapsule,
+            ),
+        };
+        let (sample_duration, max_distance) = self.mode_conf(mode)?;
+        // without knowing how, the motor is left spinning when dropped
+        let motor = match self.motor {
+            Some(motor) => Some(motor),
+            None => self.motor_control().ok(),
+        };
+        let stamper = Stamper::new(Arc::clone(&self.clock), sample_duration);
 
-    fn validate_dense(msg: &[u8]) -> bool {
-        msg[0] >> 4 == 0xa && msg[1] >> 4 == 0x5
-            && Self::checksum(&msg[2..]) == (msg[1] << 4) | (msg[0] & 0b1111)
-    }
+        // signal lidar to begin a scan
+        self.transport.write_all(req)?;
 
-    /// Thread that receives scan data
-    fn reader_thread_dense(tx: Sender<Sample>, mut transport: Box<dyn SerialPort>, nuke: Arc<AtomicBool>) {
-        let mut seeking = true;
-        let mut descriptor = [0u8; 7];
+        let (tx, rx) = buffer::bounded(self.buffer);
+        let session = ScanSession::spawn(self.transport.as_ref(), tx, rx, mode, framing, stamper, max_distance)?;
+        self.control = Arc::clone(session.control());
+        self.restore_timeout = true;
+        self.motor = motor;
 
-        transport
-            .read_exact(&mut descriptor)
-            .expect("missing descriptor");
+        Ok(session)
+    }
 
-        if descriptor != [0xa5, 0x5a, 0x54, 0x00, 0x00, 0x40, 0x85] {
-            eprintln!("Unable to read lidar stream (malformed descriptor)");
-            return;
+    /// Time per measurement and maximum range (m) of a scan mode. Firmware predating the scan
+    /// mode table only reports the former, through GetSampleRate.
+    fn mode_conf(&mut self, mode: ScanMode) -> Result<(Duration, Option<f32>), RxError> {
+        if let Ok(id) = self.scan_mode_id(mode) {
+            if let Ok(duration) = self.get_us_per_sample(id) {
+                return Ok((duration, self.get_max_distance(id).ok()));
+            }
         }
 
-        // give the lidar time to spin up
-        sleep(Duration::from_millis(1000));
-
-        const MSG_SIZE: usize = 84;
+        let rate = match self.sample_rate {
+            Some(rate) => rate,
+            None => self.get_sample_rate()?,
+        };
+        let sample_us = match mode {
+            ScanMode::Standard => rate.std_sample_duration_us,
+            ScanMode::Dense => rate.express_sample_duration_us,
+        };
+        Ok((Duration::from_micros(sample_us as u64), None))
+    }
+}
 
-        loop {
-            const BATCH: usize = S1_BAUD / (100 * MSG_SIZE);
-            let mut data = [0u8; MSG_SIZE * BATCH];
+impl Drop for Lidar {
+    /// Stops a running scan and, if a scan of this connection started it, the motor.
+    ///
+    /// Failures are ignored; call [`Lidar::stop`] first to handle them.
+    fn drop(&mut self) {
+        if self.control.state() == ScanState::Scanning {
+            let _ = self.stop(false);
+        }
+        if let Some(motor) = self.motor {
+            let _ = self.stop_motor(motor);
+        }
+    }
+}
 
-            if let Err(err) = transport.read_exact(&mut data) {
-                if nuke.load(Ordering::Relaxed) {
-                    nuke.store(false, Ordering::Relaxed);
-                    println!("Scan stopped.");
-                    return;
-                }
-                panic!("Unable to read lidar stream (malformed data): {}", err);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::laser::channel::tests::ScriptedChannel;
+    use crate::laser::session::tests::answer;
+
+    /// A lidar listing the given scan modes, recommending the last one
+    fn listing(modes: &'static [&'static str]) -> Lidar {
+        let channel = ScriptedChannel::new(move |req| match req {
+            [0xa5, 0x84, _, 0x70, ..] => answer(0x20, &[0x70, 0, 0, 0, modes.len() as u8, 0]),
+            [0xa5, 0x84, _, 0x7c, ..] => answer(0x20, &[0x7c, 0, 0, 0, modes.len() as u8 - 1, 0]),
+            [0xa5, 0x84, _, 0x7f, 0, 0, 0, id, ..] => {
+                let name = [[0x7f, 0, 0, 0].as_slice(), modes[*id as usize].as_bytes(), &[0; 4]].concat();
+                answer(0x20, &name)
             }
+            _ => Vec::new(),
+        });
+        Lidar::with_channel(Box::new(channel))
+    }
 
-            for i in 0..BATCH {
-                let slice = &data[i * MSG_SIZE..i * MSG_SIZE + MSG_SIZE];
-
-                // checks
-                if !Self::validate_dense(slice) {
-                    eprintln!("checks failed: {:x?}", slice);
-                    continue;
-                }
-
-                let cabins = &slice[4..];
-
-                // let sample = DenseSample {
-                //     start: slice[3] >> 7 != 0,
-                //     angle: (((slice[3] & 0b1111111) as u16) << 1) | (slice[1] as u16 >> 7),
-                //     distance: ,
-                // };
-                //
-                // if seeking && !sample.start {
-                //     continue;
-                // }
-                //
-                // seeking = false;
-                // tx.send(sample).unwrap();
-            }
-        }
+    #[test]
+    fn looks_up_scan_modes_by_name() {
+        let mut lidar = listing(&["Standard", "Express", "Boost", "DenseBoost"]);
+        assert_eq!(lidar.get_scan_mode_name(2).unwrap(), "Boost");
+        assert_eq!(lidar.scan_mode_id(ScanMode::Standard).unwrap(), 0);
+        assert_eq!(lidar.scan_mode_id(ScanMode::Dense).unwrap(), 3);
+    }
+
+    #[test]
+    fn falls_back_to_the_typical_mode_for_dense_scans() {
+        let mut lidar = listing(&["Express", "Standard", "Sensitivity"]);
+        assert_eq!(lidar.scan_mode_id(ScanMode::Standard).unwrap(), 1);
+        assert_eq!(lidar.scan_mode_id(ScanMode::Dense).unwrap(), 2);
+
+        let mut lidar = listing(&["Express"]);
+        assert!(matches!(lidar.scan_mode_id(ScanMode::Standard), Err(RxError::UnknownScanMode)));
     }
 }
@@ -1,8 +1,33 @@
 mod lidar;
 pub(crate) mod cmd;
 mod protocol;
+pub mod channel;
+pub mod clock;
+pub mod discovery;
+pub mod buffer;
+mod framer;
+mod session;
+mod stats;
+mod timing;
+pub mod revolution;
+pub mod supervisor;
+#[cfg(feature = "tokio")]
+pub mod tokio;
 
-pub use lidar::Lidar;
+pub use cmd::{
+    LidarConf, ConfEntry, SlLidarIpConfT, SlLidarResponseDeviceInfoT,
+    SlLidarResponseDeviceMacaddrInfoT,
+};
+pub use discovery::DetectedLidar;
+pub use buffer::{BufferConfig, DropPolicy, ScanReceiver};
+pub use lidar::{Lidar, ScanMode};
+pub use session::{ScanSession, ScanState};
+pub use stats::ScanStats;
+pub use timing::ScanTiming;
+pub use supervisor::{PortSpec, ScanEvent, Supervisor, SupervisorConfig};
+pub use protocol::Sample;
+pub use clock::{Clock, ManualClock, MonotonicClock, SystemClock};
+pub use revolution::Revolution;
 
 // LIDAR Scan Mode
 // pub struct LidarScanMode {
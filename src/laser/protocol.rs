@@ -1,3 +1,20 @@
+use crate::error::RxError;
+use crate::laser::cmd::ConfEntry::{Count, DesiredRotFreq, MacAddr, StaticIpAddr, Typical};
+use crate::laser::cmd::SlLidarCmd::GetLidarConf;
+use crate::laser::cmd::{
+    LidarConf, ConfEntry, SlLidarAnsType, SlLidarCmd, SlLidarResponseSetLidarConf,
+};
+use std::time::Duration;
+
+/// Send mode of answers to requests with a single response
+const SEND_MODE_SINGLE: u8 = 0x0;
+
+/// Response descriptor announcing a standard scan
+pub const SCAN_DESCRIPTOR: [u8; 7] = [0xa5, 0x5a, 0x05, 0x00, 0x00, 0x40, SlLidarAnsType::Measurement as u8];
+/// Response descriptor announcing a dense express scan
+pub const DENSE_DESCRIPTOR: [u8; 7] =
+    [0xa5, 0x5a, 0x54, 0x00, 0x00, 0x40, SlLidarAnsType::MeasurementDenseCapsuled as u8];
+
 #[derive(Debug)]
 pub struct ResponseDescriptor {
     pub len: u32,
@@ -5,22 +22,306 @@ pub struct ResponseDescriptor {
     pub data_type: u8,
 }
 
+impl ResponseDescriptor {
+    /// Parses the 7 byte header preceding every response
+    pub fn parse(mut bytes: [u8; 7]) -> Result<ResponseDescriptor, RxError> {
+        if bytes[0..2] != [0xa5, 0x5a] {
+            return Err(RxError::Corrupted(bytes));
+        }
+
+        let send_mode = (bytes[5] & 0b11000000) >> 6;
+        let data_type = bytes[6];
+
+        bytes[5] ^= bytes[5] & 0b11000000;
+        let len = crate::util::read_le_u32(&mut &bytes[2..6]);
+
+        Ok(ResponseDescriptor {
+            len,
+            send_mode,
+            data_type,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct Response {
     pub descriptor: ResponseDescriptor,
     pub data: Vec<u8>,
 }
 
+impl Response {
+    /// Fails unless the response is a single answer of the given type carrying at least `len`
+    /// bytes of data
+    pub(crate) fn expect(self, ans_type: SlLidarAnsType, len: usize) -> Result<Response, RxError> {
+        let descriptor = &self.descriptor;
+        if descriptor.send_mode != SEND_MODE_SINGLE || descriptor.data_type != ans_type as u8 || self.data.len() < len {
+            return Err(RxError::UnexpectedResponse(self.descriptor.data_type));
+        }
+        Ok(self)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Sample {
     pub(crate) start: bool,
-    pub(crate) intensity: u8,
-    pub(crate) angle: u16,
-    pub(crate) distance: u16,
+    /// quality of the return, unknown for dense scans
+    pub(crate) intensity: Option<u8>,
+    /// heading of the measurement (q6 degrees), clockwise as seen from above
+    pub(crate) angle_q6: u16,
+    /// range of the measurement (q2 mm), 0 if it is invalid; wider than the standard node's
+    /// field, as dense capsules report whole millimetres beyond its 16 m
+    pub(crate) distance_q2: u32,
+    /// host time the sample's data was read from the port
+    pub received: Duration,
+    /// estimated host time of the measurement
+    pub timestamp: Duration,
+}
+
+impl Sample {
+    /// Decodes a 5 byte standard scan node, `None` if its check bits are invalid
+    pub(crate) fn from_node(node: &[u8]) -> Option<Sample> {
+        let s = node[0] & 0b11;
+        if s == 0b11 || s == 0b00 || node[1] & 0b01 != 1 {
+            return None;
+        }
+
+        Some(Sample {
+            start: (node[0] & 1) != 0,
+            intensity: Some(node[0] >> 2),
+            angle_q6: ((node[2] as u16) << 7) | (node[1] as u16 >> 1),
+            distance_q2: u16::from_le_bytes([node[3], node[4]]) as u32,
+            received: Duration::ZERO,
+            timestamp: Duration::ZERO,
+        })
+    }
+
+    /// Whether the sample starts a new revolution
+    pub fn start(&self) -> bool {
+        self.start
+    }
+
+    /// Quality of the return, `None` for dense scans whose capsules don't carry it
+    pub fn intensity(&self) -> Option<u8> {
+        self.intensity
+    }
+
+    /// Heading of the measurement in degrees, clockwise as seen from above
+    pub fn angle(&self) -> f32 {
+        self.angle_q6 as f32 / 64.0
+    }
+
+    /// Range in millimetres, 0 if the measurement is invalid
+    pub fn distance(&self) -> f32 {
+        self.distance_q2 as f32 / 4.0
+    }
+
+    /// Whether the measurement carries a range
+    pub fn is_valid(&self) -> bool {
+        self.distance_q2 > 0
+    }
+
+    /// Sets the heading in degrees, wrapped into [0, 360) and rounded to the device's resolution
+    pub(crate) fn set_angle(&mut self, angle: f32) {
+        self.angle_q6 = ((angle.rem_euclid(360.0) * 64.0).round() as u32 % (360 << 6)) as u16;
+    }
+
+    /// Sets the range in millimetres, rounded to the device's resolution; invalid ranges become 0
+    pub(crate) fn set_distance(&mut self, distance: f32) {
+        self.distance_q2 = if distance.is_finite() { (distance * 4.0).round().max(0.0) as u32 } else { 0 };
+    }
+}
+
+#[cfg(test)]
+impl Sample {
+    /// A measurement of `distance` (mm) at `angle` (degrees), of quality 47
+    pub(crate) fn at(angle: f32, distance: f32) -> Sample {
+        let mut sample = Sample {
+            start: false,
+            intensity: Some(47),
+            angle_q6: 0,
+            distance_q2: 0,
+            received: Duration::ZERO,
+            timestamp: Duration::ZERO,
+        };
+        sample.set_angle(angle);
+        sample.set_distance(distance);
+        sample
+    }
 }
 
+/// One dense express scan capsule
 pub struct DenseSample {
+    /// set on the first capsule after the scan (re)started
     pub(crate) start: bool,
+    /// start angle of the capsule (q6 degrees)
     pub(crate) angle: u16,
+    /// distances of the capsule's measurements (mm)
     pub(crate) cabin: [u16; 40],
-}
\ No newline at end of file
+}
+
+impl DenseSample {
+    /// Decodes an 84 byte capsule whose checks have already passed
+    pub(crate) fn from_capsule(msg: &[u8]) -> DenseSample {
+        let start_angle = u16::from_le_bytes([msg[2], msg[3]]);
+        let mut cabin = [0u16; 40];
+        for (i, distance) in cabin.iter_mut().enumerate() {
+            *distance = u16::from_le_bytes([msg[4 + 2 * i], msg[5 + 2 * i]]);
+        }
+
+        DenseSample {
+            start: start_angle >> 15 != 0,
+            angle: start_angle & 0x7fff,
+            cabin,
+        }
+    }
+
+    /// Spreads the capsule's measurements between its start angle and that of the next capsule.
+    ///
+    /// Capsules carry no revolution flag, so `start` is left unset.
+    pub(crate) fn samples(&self, next_angle: u16) -> impl Iterator<Item = Sample> + '_ {
+        const FULL_CIRCLE: u32 = 360 << 6;
+        let diff = (next_angle as u32 + FULL_CIRCLE - self.angle as u32) % FULL_CIRCLE;
+
+        self.cabin.iter().enumerate().map(move |(i, &distance)| {
+            let angle_q6 = (self.angle as u32 + diff * i as u32 / 40) % FULL_CIRCLE;
+            Sample {
+                start: false,
+                intensity: None,
+                angle_q6: angle_q6 as u16,
+                distance_q2: distance as u32 * 4,
+                received: Duration::ZERO,
+                timestamp: Duration::ZERO,
+                }
+        })
+    }
+}
+
+/// Generates the checksum for a given message
+pub(crate) fn checksum(payload: &[u8]) -> u8 {
+    payload.iter().fold(0, |acc, x| acc ^ x)
+}
+
+/// Builds a request carrying a payload
+pub(crate) fn payload_req(cmd: SlLidarCmd, payload: &[u8]) -> Vec<u8> {
+    let mut req = vec![0xa5, cmd as u8, payload.len() as u8];
+    req.extend_from_slice(payload);
+    req.push(checksum(&req));
+    req
+}
+
+/// Builds a `GetLidarConf` request, `payload` selecting the scan mode where needed
+///
+/// Fails with [`RxError::MissingScanMode`] if the entry describes a scan mode but none is given.
+pub(crate) fn conf_req(entry: ConfEntry, payload: Option<u16>) -> Result<Vec<u8>, RxError> {
+    let mut data = (entry as u32).to_le_bytes().to_vec();
+
+    match (entry, payload) {
+        (DesiredRotFreq | Count | Typical | MacAddr | StaticIpAddr, _) => {}
+        (_, Some(mode)) => data.extend_from_slice(&(mode as u32).to_le_bytes()),
+        (_, None) => return Err(RxError::MissingScanMode(entry as u32)),
+    }
+
+    Ok(payload_req(GetLidarConf, &data))
+}
+
+/// Checks the lidar's answer to a `SetLidarConf` request
+pub(crate) fn check_set_conf(conf: &LidarConf, res: &Response) -> Result<(), RxError> {
+    if res.descriptor.data_type != SlLidarAnsType::SetLidarConf as u8 || res.data.len() < 8 {
+        return Err(RxError::UnexpectedResponse(res.descriptor.data_type));
+    }
+
+    let response = SlLidarResponseSetLidarConf::from_bytes(&res.data);
+    if response.conf_type != conf.conf_type() {
+        return Err(RxError::ConfMismatch(conf.conf_type(), response.conf_type));
+    }
+    if response.result != 0 {
+        return Err(RxError::ConfRejected(response.conf_type, response.result));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A standard scan node with valid check bits
+    pub(crate) fn node(start: bool, quality: u8, angle_q6: u16, distance_q2: u16) -> [u8; 5] {
+        let [d0, d1] = distance_q2.to_le_bytes();
+        [
+            quality << 2 | if start { 0b01 } else { 0b10 },
+            (angle_q6 as u8) << 1 | 1,
+            (angle_q6 >> 7) as u8,
+            d0,
+            d1,
+        ]
+    }
+
+    /// A dense capsule with a valid checksum
+    pub(crate) fn capsule(start: bool, angle_q6: u16, distances: [u16; 40]) -> [u8; 84] {
+        let mut msg = [0u8; 84];
+        msg[2..4].copy_from_slice(&(angle_q6 | (start as u16) << 15).to_le_bytes());
+        for (i, distance) in distances.iter().enumerate() {
+            msg[4 + 2 * i..6 + 2 * i].copy_from_slice(&distance.to_le_bytes());
+        }
+        let sum = checksum(&msg[2..]);
+        msg[0] = 0xa0 | (sum & 0xf);
+        msg[1] = 0x50 | (sum >> 4);
+        msg
+    }
+
+    #[test]
+    fn decodes_nodes() {
+        let sample = Sample::from_node(&node(true, 47, 90 * 64 + 32, 1234 * 4 + 1)).unwrap();
+        assert!(sample.start());
+        assert_eq!(sample.intensity(), Some(47));
+        assert_eq!(sample.angle(), 90.5);
+        assert_eq!(sample.distance(), 1234.25);
+        assert!(sample.is_valid());
+
+        let sample = Sample::from_node(&node(false, 0, 0, 0)).unwrap();
+        assert!(!sample.start());
+        assert!(!sample.is_valid());
+    }
+
+    #[test]
+    fn rejects_nodes_with_bad_check_bits() {
+        let mut bad = node(true, 10, 64, 400);
+        // start and inverted start agree
+        bad[0] |= 0b11;
+        assert!(Sample::from_node(&bad).is_none());
+
+        let mut bad = node(true, 10, 64, 400);
+        bad[1] &= !1;
+        assert!(Sample::from_node(&bad).is_none());
+    }
+
+    #[test]
+    fn spreads_dense_capsules() {
+        let distances = core::array::from_fn(|i| 1000 + i as u16);
+        let capsule = DenseSample::from_capsule(&capsule(true, 350 * 64, distances));
+        assert!(capsule.start);
+        assert_eq!(capsule.angle, 350 * 64);
+
+        // the next capsule starts 20 degrees later, past the wrap-around
+        let samples: Vec<Sample> = capsule.samples(10 * 64).collect();
+        assert_eq!(samples.len(), 40);
+        assert_eq!(samples[0].angle(), 350.0);
+        assert_eq!(samples[1].angle(), 350.5);
+        assert_eq!(samples[20].angle(), 0.0);
+        assert_eq!(samples[39].angle(), 9.5);
+        assert_eq!(samples[0].distance(), 1000.0);
+        assert_eq!(samples[39].distance(), 1039.0);
+        assert!(samples.iter().all(|s| !s.start() && s.intensity().is_none()));
+    }
+
+    #[test]
+    fn conf_requests_need_a_mode_for_mode_entries() {
+        assert_eq!(conf_req(ConfEntry::Count, None).unwrap(), [0xa5, 0x84, 4, 0x70, 0, 0, 0, 0x55]);
+        assert_eq!(
+            conf_req(ConfEntry::UsPerSample, Some(2)).unwrap(),
+            [0xa5, 0x84, 8, 0x71, 0, 0, 0, 2, 0, 0, 0, 0x5a]
+        );
+        assert!(matches!(conf_req(ConfEntry::UsPerSample, None), Err(RxError::MissingScanMode(0x71))));
+    }
+}
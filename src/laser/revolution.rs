@@ -0,0 +1,76 @@
+use crate::laser::protocol::Sample;
+use std::time::Duration;
+
+/// The samples of one full rotation, starting at a sample with `start` set
+#[derive(Debug, Clone, Default)]
+pub struct Revolution {
+    pub samples: Vec<Sample>,
+}
+
+impl Revolution {
+    /// Measurement time of the first sample
+    pub fn start_time(&self) -> Option<Duration> {
+        self.samples.first().map(|s| s.timestamp)
+    }
+
+    /// Measurement time of the last sample
+    pub fn end_time(&self) -> Option<Duration> {
+        self.samples.last().map(|s| s.timestamp)
+    }
+
+    /// Time the rotation took to measure
+    pub fn duration(&self) -> Duration {
+        match (self.start_time(), self.end_time()) {
+            (Some(start), Some(end)) => end.saturating_sub(start),
+            _ => Duration::ZERO,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+/// Groups a sample stream into revolutions, see [`revolutions`]
+pub struct Revolutions<I> {
+    samples: I,
+    /// start of the revolution after the one being assembled
+    next: Option<Sample>,
+}
+
+/// Groups samples into revolutions.
+///
+/// Samples before the first start are skipped. The last, incomplete revolution is yielded when
+/// the stream ends.
+pub fn revolutions<I: IntoIterator<Item = Sample>>(samples: I) -> Revolutions<I::IntoIter> {
+    Revolutions {
+        samples: samples.into_iter(),
+        next: None,
+    }
+}
+
+impl<I: Iterator<Item = Sample>> Iterator for Revolutions<I> {
+    type Item = Revolution;
+
+    fn next(&mut self) -> Option<Revolution> {
+        let first = match self.next.take() {
+            Some(sample) => sample,
+            None => self.samples.find(|s| s.start)?,
+        };
+
+        let mut revolution = Revolution { samples: vec![first] };
+        for sample in self.samples.by_ref() {
+            if sample.start {
+                self.next = Some(sample);
+                break;
+            }
+            revolution.samples.push(sample);
+        }
+
+        Some(revolution)
+    }
+}
@@ -0,0 +1,499 @@
+use crate::error::RxError;
+use crate::laser::buffer::{Closer, Iter, ScanReceiver, ScanSender};
+use crate::laser::channel::{is_timeout, Channel};
+use crate::laser::clock::Stamper;
+use crate::laser::cmd::SlLidarCmd::Stop;
+use crate::laser::framer::{Decoder, Framer, Framing};
+use crate::laser::protocol::{DENSE_DESCRIPTOR, SCAN_DESCRIPTOR};
+use crate::laser::revolution;
+use crate::laser::revolution::Revolutions;
+use crate::laser::stats::ScanStats;
+use crate::laser::lidar::ScanMode;
+use crate::laser::timing::{RateTracker, ScanTiming};
+use std::io;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long the port must stay silent for a stop to be considered complete
+pub(crate) const QUIET: Duration = Duration::from_millis(50);
+/// How long stopping may take before giving up on the reader thread
+pub(crate) const STOP_DEADLINE: Duration = Duration::from_secs(2);
+/// How long to wait for a reader thread to stop, which may first finish a read of up to `QUIET`
+/// and then drain a chatty port until `STOP_DEADLINE`
+pub(crate) const STOP_WAIT: Duration = STOP_DEADLINE.saturating_add(QUIET).saturating_add(Duration::from_millis(250));
+
+/// Lifecycle of a scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanState {
+    /// No scan has been started
+    Idle,
+    /// The reader thread is receiving samples
+    Scanning,
+    /// The scan ended, either stopped or because the stream failed
+    Stopped,
+}
+
+/// State shared between the driver, a scan session and its reader thread
+pub(crate) struct ScanControl {
+    state: Mutex<ScanState>,
+    changed: Condvar,
+    /// Should nuke `reader_thread`?
+    nuke: AtomicBool,
+    /// why the reader thread gave up, if it did
+    error: Mutex<Option<RxError>>,
+    /// the scan's sample buffer, closed when stopping so that a blocked reader wakes up
+    buffer: Option<Closer>,
+}
+
+impl ScanControl {
+    pub(crate) fn new(buffer: Option<Closer>) -> ScanControl {
+        ScanControl {
+            state: Mutex::new(ScanState::Idle),
+            changed: Condvar::new(),
+            nuke: AtomicBool::new(false),
+            error: Mutex::new(None),
+            buffer,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ScanState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn state(&self) -> ScanState {
+        *self.lock()
+    }
+
+    pub(crate) fn set_state(&self, state: ScanState) {
+        *self.lock() = state;
+        self.changed.notify_all();
+    }
+
+    pub(crate) fn error(&self) -> Option<RxError> {
+        self.error.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn set_error(&self, err: RxError) {
+        *self.error.lock().unwrap_or_else(|e| e.into_inner()) = Some(err);
+    }
+
+    /// Asks the reader thread to wind down
+    ///
+    /// Closes the sample buffer, which the consumer can still drain, so that a reader waiting
+    /// for space under [`crate::laser::DropPolicy::Block`] notices too.
+    pub(crate) fn nuke(&self) {
+        self.nuke.store(true, Ordering::Relaxed);
+        if let Some(buffer) = &self.buffer {
+            buffer.close();
+        }
+    }
+
+    fn nuked(&self) -> bool {
+        self.nuke.load(Ordering::Relaxed)
+    }
+
+    /// Waits until no scan is running, at most `timeout` if given
+    pub(crate) fn wait_stopped(&self, timeout: Option<Duration>) -> Result<(), RxError> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut state = self.lock();
+
+        while *state == ScanState::Scanning {
+            state = match deadline {
+                None => self.changed.wait(state).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(RxError::TimedOut);
+                    }
+                    self.changed
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+            };
+        }
+
+        Ok(())
+    }
+}
+
+/// A running (or finished) scan, returned by [`crate::laser::Lidar::start_scan`].
+///
+/// Owns the receiving end of the sample buffer, the scan's counters and its reader thread.
+/// Dropping the session stops the scan.
+pub struct ScanSession {
+    rx: ScanReceiver,
+    reader: Option<JoinHandle<()>>,
+    control: Arc<ScanControl>,
+    /// handle used to send Stop
+    transport: Box<dyn Channel>,
+    mode: ScanMode,
+    timing: ScanTiming,
+    /// maximum range of the mode (m), if the lidar reports one
+    max_distance: Option<f32>,
+}
+
+impl ScanSession {
+    /// Spawns the reader thread for a scan that has just been requested
+    pub(crate) fn spawn(
+        transport: &dyn Channel,
+        tx: ScanSender,
+        rx: ScanReceiver,
+        mode: ScanMode,
+        framing: Framing,
+        stamper: Stamper,
+        max_distance: Option<f32>,
+    ) -> Result<ScanSession, RxError> {
+        let timing = ScanTiming::new(framing, stamper.sample_duration());
+        let control = Arc::new(ScanControl::new(Some(rx.closer())));
+        let reader_transport = transport.try_clone()?;
+        let reader_control = Arc::clone(&control);
+
+        control.set_state(ScanState::Scanning);
+        let reader = thread::spawn(move || {
+            // the sender is only dropped with the closure, so that the consumer sees the end of
+            // the stream after the error and the state are set
+            let _stopped = StoppedOnExit(&reader_control);
+            if let Err(err) = reader_thread(&tx, reader_transport, &reader_control, framing, stamper) {
+                reader_control.set_error(err);
+            }
+        });
+
+        Ok(ScanSession {
+            rx,
+            reader: Some(reader),
+            control,
+            transport: transport.try_clone()?,
+            mode,
+            timing,
+            max_distance,
+        })
+    }
+
+    pub(crate) fn control(&self) -> &Arc<ScanControl> {
+        &self.control
+    }
+
+    /// The buffer receiving this scan's samples
+    pub fn receiver(&self) -> &ScanReceiver {
+        &self.rx
+    }
+
+    /// Iterates over samples until the scan ends
+    pub fn iter(&self) -> Iter<'_> {
+        self.rx.iter()
+    }
+
+    /// Iterates over full revolutions until the scan ends
+    pub fn revolutions(&self) -> Revolutions<Iter<'_>> {
+        revolution::revolutions(self.rx.iter())
+    }
+
+    /// Counters of this scan
+    pub fn stats(&self) -> &Arc<ScanStats> {
+        self.rx.stats()
+    }
+
+    /// Which scan this is
+    pub fn mode(&self) -> ScanMode {
+        self.mode
+    }
+
+    /// Maximum range of the scan's mode in metres, as reported by the lidar when the scan started
+    pub fn max_distance(&self) -> Option<f32> {
+        self.max_distance
+    }
+
+    /// Data rate expected from the lidar
+    pub fn timing(&self) -> ScanTiming {
+        self.timing
+    }
+
+    pub fn state(&self) -> ScanState {
+        self.control.state()
+    }
+
+    /// Whether the reader thread is still receiving samples
+    pub fn is_running(&self) -> bool {
+        self.state() == ScanState::Scanning
+    }
+
+    /// Why the scan ended on its own (stream failure or lost connection), or why the lidar did
+    /// not go quiet after stopping
+    pub fn error(&self) -> Option<RxError> {
+        self.control.error()
+    }
+
+    /// Stops the scan
+    ///
+    /// Sends Stop, waits for in-flight data to drain and joins the reader thread. Samples
+    /// already buffered can still be received afterwards.
+    pub fn stop(&mut self) -> Result<(), RxError> {
+        if self.reader.is_none() {
+            return Ok(());
+        }
+
+        if self.control.state() == ScanState::Scanning {
+            self.control.nuke();
+            self.transport.write_all(&[0xa5, Stop as u8])?;
+        }
+
+        // the reader thread drains the port itself before exiting
+        self.control.wait_stopped(Some(STOP_WAIT))?;
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ScanSession {
+    /// Stops the scan, ignoring failures; call [`ScanSession::stop`] first to handle them
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// Marks the scan stopped when the reader thread exits, even by panicking
+struct StoppedOnExit<'a>(&'a ScanControl);
+
+impl Drop for StoppedOnExit<'_> {
+    fn drop(&mut self) {
+        self.0.set_state(ScanState::Stopped);
+    }
+}
+
+/// Discards incoming bytes until the channel stays silent for `QUIET`
+pub(crate) fn drain(transport: &mut dyn Channel) -> Result<(), RxError> {
+    transport.set_timeout(QUIET)?;
+    let deadline = Instant::now() + STOP_DEADLINE;
+    let mut data = [0u8; 4096];
+
+    loop {
+        match transport.read(&mut data) {
+            Ok(0) => return Ok(()),
+            Ok(_) if Instant::now() < deadline => continue,
+            Ok(_) => return Err(RxError::TimedOut),
+            Err(err) if is_timeout(&err) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Thread that receives scan data
+///
+/// Returns once stopped or the receiver is dropped, or with the error that ended the stream.
+fn reader_thread(
+    tx: &ScanSender,
+    mut transport: Box<dyn Channel>,
+    control: &ScanControl,
+    framing: Framing,
+    stamper: Stamper,
+) -> Result<(), RxError> {
+    let mut seeking = true;
+    let mut descriptor = [0u8; 7];
+
+    if let Err(err) = transport.read_exact(&mut descriptor) {
+        return if control.nuked() { Ok(()) } else { Err(err.into()) };
+    }
+
+    let expected = match framing {
+        Framing::Node => SCAN_DESCRIPTOR,
+        Framing::DenseCapsule => DENSE_DESCRIPTOR,
+    };
+    if descriptor != expected {
+        return Err(RxError::Corrupted(descriptor));
+    }
+
+    let mut framer = Framer::new(framing);
+    let mut decoder = Decoder::new(framing);
+    let mut samples = Vec::new();
+    let timing = ScanTiming::new(framing, stamper.sample_duration());
+    let mut rates = RateTracker::new(timing);
+    let mut data = vec![0u8; timing.read_size()];
+
+    // reads must return regularly so that a stop is noticed even while the port is quiet
+    transport.set_timeout(QUIET)?;
+
+    loop {
+        if control.nuked() || tx.is_closed() {
+            if !control.nuked() {
+                // the receiver was dropped without stopping the scan
+                let _ = transport.write_all(&[0xa5, Stop as u8]);
+            }
+            // a port that does not go quiet is reported as the scan's error
+            return drain(transport.as_mut());
+        }
+
+        let read = transport.read(&mut data);
+        let received = stamper.now();
+        let len = match read {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Ok(len) => len,
+            Err(err) if is_timeout(&err) => continue,
+            Err(_) if control.nuked() => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+
+        // re-align on packet boundaries, skipping anything corrupted
+        framer.push(&data[..len]);
+        while let Some(packet) = framer.next_packet() {
+            decoder.decode(packet, &mut samples);
+        }
+        tx.stats().set_framing(framer.discarded, framer.resyncs);
+
+        let newest = samples.len() + decoder.pending();
+        for (i, mut sample) in samples.drain(..).enumerate() {
+            stamper.stamp(&mut sample, received, newest - 1 - i);
+            if seeking && !sample.start {
+                continue;
+            }
+
+            seeking = false;
+            rates.track(sample.start, sample.timestamp, tx.stats());
+            if tx.send(sample).is_err() {
+                // nobody is listening anymore
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::laser::channel::tests::ScriptedChannel;
+    use crate::laser::cmd::SlLidarCmd::HQMotorSpeedCtrl;
+    use crate::laser::protocol;
+    use crate::laser::protocol::tests::node;
+    use crate::laser::Lidar;
+
+    /// Descriptor and data of an answer
+    pub(crate) fn answer(ans_type: u8, data: &[u8]) -> Vec<u8> {
+        let mut res = vec![0xa5, 0x5a, data.len() as u8, 0x00, 0x00, 0x00, ans_type];
+        res.extend_from_slice(data);
+        res
+    }
+
+    /// A lidar of the given model that reports its scan modes, optionally answering Scan
+    pub(crate) fn lidar(model: u8, answers_scan: bool) -> (Lidar, ScriptedChannel) {
+        let channel = ScriptedChannel::new(move |req| match req {
+            [0xa5, 0x50] => answer(0x04, &[[model, 0x1d, 0x01, 0x12].as_slice(), &[0; 16]].concat()),
+            [0xa5, 0x52] => answer(0x06, &[0, 0, 0]),
+            // Standard and DenseBoost, the latter recommended
+            [0xa5, 0x84, _, 0x70, ..] => answer(0x20, &[0x70, 0, 0, 0, 2, 0]),
+            [0xa5, 0x84, _, 0x7c, ..] => answer(0x20, &[0x7c, 0, 0, 0, 1, 0]),
+            [0xa5, 0x84, _, 0x7f, 0, 0, 0, 0, ..] => answer(0x20, b"\x7f\0\0\0Standard\0"),
+            [0xa5, 0x84, _, 0x7f, 0, 0, 0, 1, ..] => answer(0x20, b"\x7f\0\0\0DenseBoost\0"),
+            // 250 µs per sample and 40 m of range, both in Q8
+            [0xa5, 0x84, _, 0x71, ..] => answer(0x20, &[0x71, 0, 0, 0, 0x00, 0xfa, 0x00, 0x00]),
+            [0xa5, 0x84, _, 0x74, ..] => answer(0x20, &[0x74, 0, 0, 0, 0x00, 0x28, 0x00, 0x00]),
+            // an accessory board without motor control
+            [0xa5, 0xff, ..] => answer(0xff, &[0, 0, 0, 0]),
+            [0xa5, 0x20] if answers_scan => SCAN_DESCRIPTOR.to_vec(),
+            _ => Vec::new(),
+        });
+        (Lidar::with_channel(Box::new(channel.clone())), channel)
+    }
+
+    /// Keeps sending measurements until `quiet_after` has passed since Stop was written
+    fn chatter(channel: ScriptedChannel, quiet_after: Duration) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let mut stopped = None;
+            loop {
+                if stopped.is_none() && channel.written().contains(&vec![0xa5, Stop as u8]) {
+                    stopped = Some(Instant::now());
+                }
+                if stopped.is_some_and(|at| at.elapsed() >= quiet_after) {
+                    return;
+                }
+                channel.push(&node(false, 10, 64, 400));
+                thread::sleep(Duration::from_millis(5));
+            }
+        })
+    }
+
+    #[test]
+    fn stop_waits_for_the_port_to_go_quiet() {
+        let (mut lidar, channel) = lidar(0x61, true);
+        let mut session = lidar.start_scan().unwrap();
+        let device = chatter(channel.clone(), Duration::from_millis(200));
+
+        let start = Instant::now();
+        session.stop().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(session.state(), ScanState::Stopped);
+        assert!(session.error().is_none());
+        device.join().unwrap();
+
+        // nothing left over garbles the next answer
+        assert_eq!(lidar.get_info().unwrap().model, 0x61);
+    }
+
+    #[test]
+    fn stop_gives_up_on_a_port_that_never_goes_quiet() {
+        let (mut lidar, channel) = lidar(0x61, true);
+        let mut session = lidar.start_scan().unwrap();
+        let device = chatter(channel, STOP_WAIT);
+
+        let start = Instant::now();
+        session.stop().unwrap();
+        assert!(start.elapsed() >= STOP_DEADLINE && start.elapsed() < STOP_WAIT);
+        assert_eq!(session.state(), ScanState::Stopped);
+        assert!(matches!(session.error(), Some(RxError::TimedOut)));
+        device.join().unwrap();
+    }
+
+    #[test]
+    fn stops_a_scan_that_never_answered() {
+        let (mut lidar, _channel) = lidar(0x61, false);
+        let mut session = lidar.start_scan().unwrap();
+
+        session.stop().unwrap();
+        assert_eq!(session.state(), ScanState::Stopped);
+        assert!(session.error().is_none());
+    }
+
+    #[test]
+    fn requests_wait_for_the_scan_to_stop() {
+        let (mut lidar, _channel) = lidar(0x61, true);
+        assert_eq!(lidar.scan_state(), ScanState::Idle);
+
+        let session = lidar.start_scan().unwrap();
+        assert_eq!(lidar.scan_state(), ScanState::Scanning);
+        assert!(matches!(lidar.get_info(), Err(RxError::ScanInProgress)));
+        assert!(matches!(lidar.start_scan(), Err(RxError::ScanInProgress)));
+
+        lidar.stop(false).unwrap();
+        assert_eq!(lidar.scan_state(), ScanState::Stopped);
+        assert_eq!(session.state(), ScanState::Stopped);
+        assert_eq!(lidar.get_info().unwrap().model, 0x61);
+    }
+
+    #[test]
+    fn dropping_the_lidar_stops_scan_and_motor() {
+        let (mut lidar, channel) = lidar(0x61, true);
+        let session = lidar.start_scan().unwrap();
+        drop(lidar);
+
+        let written = channel.written();
+        let scan = written.iter().position(|req| req == &[0xa5, 0x20]).unwrap();
+        assert!(written[scan..].contains(&vec![0xa5, Stop as u8]));
+        assert_eq!(written.last().unwrap(), &protocol::payload_req(HQMotorSpeedCtrl, &[0, 0]));
+        assert_eq!(session.state(), ScanState::Stopped);
+    }
+
+    #[test]
+    fn dropping_the_lidar_stops_a_dtr_driven_motor() {
+        let (mut lidar, channel) = lidar(0x18, true);
+        let session = lidar.start_scan().unwrap();
+        drop(lidar);
+
+        assert_eq!(channel.dtr(), Some(true));
+        assert_eq!(session.state(), ScanState::Stopped);
+    }
+}
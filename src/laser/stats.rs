@@ -0,0 +1,66 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Counters shared between a scan's reader thread and its consumer
+#[derive(Debug, Default)]
+pub struct ScanStats {
+    /// samples discarded by the buffer's drop policy
+    dropped: AtomicU64,
+    /// bytes skipped while searching for packet boundaries
+    discarded_bytes: AtomicU64,
+    /// times the stream lost packet alignment
+    resyncs: AtomicU64,
+    /// revolutions per second over the last revolution (f64 bits)
+    rotation_hz: AtomicU64,
+    /// samples per second over the last revolution (f64 bits)
+    sample_rate: AtomicU64,
+    /// the last revolution delivered noticeably fewer samples than the lidar measures
+    lagging: AtomicBool,
+}
+
+impl ScanStats {
+    /// Number of samples discarded by the drop policy
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Number of bytes skipped to re-align the stream after corruption
+    pub fn discarded_bytes(&self) -> u64 {
+        self.discarded_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Number of times a corrupted packet forced a search for the next valid one
+    pub fn resyncs(&self) -> u64 {
+        self.resyncs.load(Ordering::Relaxed)
+    }
+
+    /// Rotation frequency measured over the last revolution, zero before the second one
+    pub fn rotation_hz(&self) -> f64 {
+        f64::from_bits(self.rotation_hz.load(Ordering::Relaxed))
+    }
+
+    /// Samples per second delivered over the last revolution
+    pub fn sample_rate(&self) -> f64 {
+        f64::from_bits(self.sample_rate.load(Ordering::Relaxed))
+    }
+
+    /// Whether fewer samples arrive than the lidar's reported sample rate promises, i.e. the link
+    /// or the host can't keep up
+    pub fn is_lagging(&self) -> bool {
+        self.lagging.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_dropped(&self, n: u64) {
+        self.dropped.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_framing(&self, discarded_bytes: u64, resyncs: u64) {
+        self.discarded_bytes.store(discarded_bytes, Ordering::Relaxed);
+        self.resyncs.store(resyncs, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_rates(&self, rotation_hz: f64, sample_rate: f64, lagging: bool) {
+        self.rotation_hz.store(rotation_hz.to_bits(), Ordering::Relaxed);
+        self.sample_rate.store(sample_rate.to_bits(), Ordering::Relaxed);
+        self.lagging.store(lagging, Ordering::Relaxed);
+    }
+}
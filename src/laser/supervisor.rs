@@ -0,0 +1,320 @@
+use crate::error::RxError;
+use crate::laser::buffer::BufferConfig;
+use crate::laser::discovery;
+use crate::laser::lidar::{Lidar, ScanMode};
+use crate::laser::protocol::Sample;
+use crate::laser::session::ScanSession;
+use serialport::SerialPortType;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::Duration;
+
+/// Where to find the lidar's serial port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortSpec {
+    /// A fixed port name such as `/dev/ttyUSB0` or `COM3`
+    Path(String),
+    /// The first USB serial adapter matching these ids, wherever it enumerates
+    Usb {
+        vid: u16,
+        pid: u16,
+        /// only match the adapter with this serial number
+        serial_number: Option<String>,
+    },
+}
+
+impl PortSpec {
+    /// Looks up the port name currently matching this spec
+    pub fn resolve(&self) -> Result<String, RxError> {
+        let (vid, pid, serial_number) = match self {
+            PortSpec::Path(path) => return Ok(path.clone()),
+            PortSpec::Usb { vid, pid, serial_number } => (*vid, *pid, serial_number),
+        };
+
+        serialport::available_ports()?
+            .into_iter()
+            .find(|port| match &port.port_type {
+                SerialPortType::UsbPort(info) => {
+                    info.vid == vid
+                        && info.pid == pid
+                        && (serial_number.is_none() || info.serial_number == *serial_number)
+                }
+                _ => false,
+            })
+            .map(|port| port.port_name)
+            .ok_or(RxError::PortNotFound)
+    }
+}
+
+/// What a [`Supervisor`] hands to its consumer
+#[derive(Debug, Clone)]
+pub enum ScanEvent {
+    Sample(Sample),
+    /// The scan stopped delivering data, a reconnection follows
+    Disconnected(RxError),
+    /// The scan is running again after `attempts` tries
+    Reconnected { attempts: u32 },
+}
+
+/// Reconnection behaviour of a [`Supervisor`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SupervisorConfig {
+    pub mode: ScanMode,
+    /// Baudrate of the serial port, probed from [`discovery::BAUDRATES`] on the first connection
+    /// if not given
+    pub baudrate: Option<u32>,
+    /// How long to wait between attempts to reopen the port
+    pub retry_interval: Duration,
+    /// Give up after this many failed attempts in a row
+    pub max_attempts: Option<u32>,
+    /// How long the scan may stay silent before the connection is considered lost
+    pub stall_timeout: Duration,
+    pub buffer: BufferConfig,
+}
+
+impl Default for SupervisorConfig {
+    /// Standard scan at a probed baudrate, retrying every second forever
+    fn default() -> Self {
+        SupervisorConfig {
+            mode: ScanMode::Standard,
+            baudrate: None,
+            retry_interval: Duration::from_secs(1),
+            max_attempts: None,
+            // leaves the motor time to spin up after a reconnection
+            stall_timeout: Duration::from_secs(3),
+            buffer: BufferConfig::default(),
+        }
+    }
+}
+
+/// Opens a new connection to the lidar
+type Opener = Box<dyn FnMut() -> Result<Lidar, RxError> + Send>;
+
+/// Keeps a serial lidar scanning across disconnects.
+///
+/// Iterating yields samples of the current scan. When the scan fails or stalls (e.g. the USB
+/// adapter re-enumerated), the supervisor emits [`ScanEvent::Disconnected`], reopens the port,
+/// checks the lidar's health and restarts the scan, then emits [`ScanEvent::Reconnected`].
+/// Reconnecting happens on the consuming thread, inside [`Supervisor::next_event`].
+pub struct Supervisor {
+    open: Opener,
+    config: SupervisorConfig,
+    // the session must be dropped (stopped) before the lidar
+    session: Option<ScanSession>,
+    lidar: Option<Lidar>,
+    /// failed attempts since the connection was lost
+    attempts: u32,
+    last_error: Option<RxError>,
+    stopped: bool,
+}
+
+impl Supervisor {
+    /// Opens the lidar and starts scanning.
+    ///
+    /// The first connection is not retried, so that a wrong port fails right away. A probed
+    /// baudrate is kept for reconnections.
+    pub fn start(spec: PortSpec, config: SupervisorConfig) -> Result<Supervisor, RxError> {
+        let mut baudrate = config.baudrate;
+        let open = move || {
+            let port = spec.resolve()?;
+            let rate = match baudrate {
+                Some(rate) => rate,
+                None => discovery::probe_baudrate(&port)?.0,
+            };
+            baudrate = Some(rate);
+            Ok(Lidar::open(&port, rate)?)
+        };
+
+        Self::with_opener(open, config)
+    }
+
+    /// Like [`Supervisor::start`], connecting through `open`
+    pub(crate) fn with_opener(
+        open: impl FnMut() -> Result<Lidar, RxError> + Send + 'static,
+        config: SupervisorConfig,
+    ) -> Result<Supervisor, RxError> {
+        let mut supervisor = Supervisor {
+            open: Box::new(open),
+            config,
+            session: None,
+            lidar: None,
+            attempts: 0,
+            last_error: None,
+            stopped: false,
+        };
+        supervisor.connect()?;
+        Ok(supervisor)
+    }
+
+    /// The running scan, if connected
+    pub fn session(&self) -> Option<&ScanSession> {
+        self.session.as_ref()
+    }
+
+    /// The error that made the most recent reconnection attempt fail
+    pub fn last_error(&self) -> Option<&RxError> {
+        self.last_error.as_ref()
+    }
+
+    /// Opens the port, checks health and starts the configured scan
+    fn connect(&mut self) -> Result<(), RxError> {
+        let mut lidar = (self.open)()?;
+        // anything still streaming from before the loss would garble the answers
+        lidar.stop(false)?;
+
+        let health = lidar.get_health()?;
+        if health.status == 2 {
+            return Err(RxError::DeviceError(health.error_code));
+        }
+
+        lidar.set_buffer(self.config.buffer);
+        let session = lidar.start_scan_mode(self.config.mode)?;
+
+        self.session = Some(session);
+        self.lidar = Some(lidar);
+        Ok(())
+    }
+
+    /// Blocks until the next event.
+    ///
+    /// Returns `None` once [`SupervisorConfig::max_attempts`] reconnection attempts failed in a
+    /// row (see [`Supervisor::last_error`]) or the supervisor was stopped.
+    pub fn next_event(&mut self) -> Option<ScanEvent> {
+        loop {
+            if let Some(session) = &self.session {
+                let err = match session.receiver().recv_timeout(self.config.stall_timeout) {
+                    Ok(sample) => return Some(ScanEvent::Sample(sample)),
+                    Err(RecvTimeoutError::Timeout) => RxError::TimedOut,
+                    // a scan that ended without error was stopped on purpose
+                    Err(RecvTimeoutError::Disconnected) => session.error()?,
+                };
+
+                self.session = None;
+                self.lidar = None;
+                self.attempts = 0;
+                return Some(ScanEvent::Disconnected(err));
+            }
+
+            let exhausted = self.config.max_attempts.is_some_and(|max| self.attempts >= max);
+            if self.stopped || exhausted {
+                return None;
+            }
+            if self.attempts > 0 {
+                thread::sleep(self.config.retry_interval);
+            }
+
+            self.attempts += 1;
+            match self.connect() {
+                Ok(()) => {
+                    self.last_error = None;
+                    return Some(ScanEvent::Reconnected { attempts: self.attempts });
+                }
+                Err(err) => self.last_error = Some(err),
+            }
+        }
+    }
+
+    /// Stops the scan and the motor; no further events are produced
+    pub fn stop(&mut self) -> Result<(), RxError> {
+        if let Some(mut session) = self.session.take() {
+            session.stop()?;
+        }
+        self.stopped = true;
+        self.lidar = None;
+        Ok(())
+    }
+}
+
+impl Iterator for Supervisor {
+    type Item = ScanEvent;
+
+    fn next(&mut self) -> Option<ScanEvent> {
+        self.next_event()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::laser::channel::tests::ScriptedChannel;
+    use crate::laser::protocol::tests::node;
+    use crate::laser::session::tests::lidar;
+    use std::sync::{Arc, Mutex};
+
+    /// Sends the start of a revolution, long enough for the framer to lock on
+    fn revolution(channel: &ScriptedChannel, angle_q6: u16) {
+        for i in 0..4 {
+            channel.push(&node(i == 0, 10, angle_q6 + i * 64, 400));
+        }
+    }
+
+    /// Skips samples up to the next connection event
+    fn next_change(supervisor: &mut Supervisor) -> Option<ScanEvent> {
+        supervisor.find(|event| !matches!(event, ScanEvent::Sample(_)))
+    }
+
+    fn config(max_attempts: Option<u32>) -> SupervisorConfig {
+        SupervisorConfig {
+            retry_interval: Duration::from_millis(10),
+            max_attempts,
+            stall_timeout: Duration::from_millis(500),
+            ..SupervisorConfig::default()
+        }
+    }
+
+    #[test]
+    fn resolves_fixed_paths() {
+        assert_eq!(PortSpec::Path("/dev/ttyUSB0".into()).resolve().unwrap(), "/dev/ttyUSB0");
+    }
+
+    #[test]
+    fn reconnects_after_the_stream_ends() {
+        let channels: Arc<Mutex<Vec<ScriptedChannel>>> = Arc::default();
+        let opened = Arc::clone(&channels);
+        let mut supervisor = Supervisor::with_opener(
+            move || {
+                let (lidar, channel) = lidar(0x61, true);
+                opened.lock().unwrap().push(channel);
+                Ok(lidar)
+            },
+            config(None),
+        )
+        .unwrap();
+        let channel = |i: usize| channels.lock().unwrap()[i].clone();
+
+        revolution(&channel(0), 64);
+        assert!(matches!(supervisor.next_event(), Some(ScanEvent::Sample(sample)) if sample.start()));
+
+        // the adapter went away
+        channel(0).close();
+        assert!(matches!(next_change(&mut supervisor), Some(ScanEvent::Disconnected(RxError::PortError(_)))));
+        assert!(matches!(supervisor.next_event(), Some(ScanEvent::Reconnected { attempts: 1 })));
+
+        revolution(&channel(1), 128);
+        assert!(matches!(supervisor.next_event(), Some(ScanEvent::Sample(sample)) if sample.angle() == 2.0));
+
+        supervisor.stop().unwrap();
+        assert!(supervisor.next_event().is_none());
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let mut opened = 0;
+        let mut supervisor = Supervisor::with_opener(
+            move || {
+                opened += 1;
+                match opened {
+                    1 => Ok(lidar(0x61, true).0),
+                    _ => Err(RxError::PortNotFound),
+                }
+            },
+            config(Some(2)),
+        )
+        .unwrap();
+
+        // no data arrives within the stall timeout
+        assert!(matches!(supervisor.next_event(), Some(ScanEvent::Disconnected(RxError::TimedOut))));
+        assert!(supervisor.next_event().is_none());
+        assert!(matches!(supervisor.last_error(), Some(RxError::PortNotFound)));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::laser::framer::Framing;
+use crate::laser::stats::ScanStats;
+use std::time::Duration;
+
+/// Smallest and largest read issued by a reader
+const MIN_READ: usize = 64;
+const MAX_READ: usize = 4096;
+/// How much data a single read should cover, keeping receive timestamps fine-grained
+const READ_SPAN: Duration = Duration::from_millis(5);
+/// Share of the expected sample rate below which the stream counts as lagging
+const LAG_RATIO: f64 = 0.9;
+
+/// Expected data rate of a scan, derived from the lidar's reported sample duration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanTiming {
+    /// time the lidar takes per measurement
+    pub sample_duration: Duration,
+    /// bytes per packet of the scan's format
+    pub packet_len: usize,
+    /// measurements carried by one packet
+    pub samples_per_packet: usize,
+}
+
+impl ScanTiming {
+    pub(crate) fn new(framing: Framing, sample_duration: Duration) -> ScanTiming {
+        ScanTiming {
+            sample_duration,
+            packet_len: framing.len(),
+            samples_per_packet: match framing {
+                Framing::Node => 1,
+                Framing::DenseCapsule => 40,
+            },
+        }
+    }
+
+    /// Measurements per second, zero if the sample duration is unknown
+    pub fn samples_per_second(&self) -> f64 {
+        if self.sample_duration.is_zero() {
+            return 0.0;
+        }
+        1.0 / self.sample_duration.as_secs_f64()
+    }
+
+    /// Bytes per second the lidar sends while scanning
+    pub fn bytes_per_second(&self) -> f64 {
+        self.samples_per_second() * self.packet_len as f64 / self.samples_per_packet as f64
+    }
+
+    /// Measurements expected over `elapsed`
+    pub fn expected_samples(&self, elapsed: Duration) -> f64 {
+        self.samples_per_second() * elapsed.as_secs_f64()
+    }
+
+    /// Read size covering about `READ_SPAN` of data, in whole packets
+    pub(crate) fn read_size(&self) -> usize {
+        let bytes = (self.bytes_per_second() * READ_SPAN.as_secs_f64()) as usize;
+        let packets = bytes.div_ceil(self.packet_len).max(1);
+        (packets * self.packet_len).clamp(MIN_READ, MAX_READ)
+    }
+}
+
+/// Measures rotation and sample rate from revolution starts, for [`ScanStats`]
+pub(crate) struct RateTracker {
+    timing: ScanTiming,
+    /// timestamp of the last revolution start
+    last_start: Option<Duration>,
+    /// samples since the last revolution start
+    samples: u64,
+}
+
+impl RateTracker {
+    pub(crate) fn new(timing: ScanTiming) -> RateTracker {
+        RateTracker {
+            timing,
+            last_start: None,
+            samples: 0,
+        }
+    }
+
+    /// Accounts for a sample about to be delivered
+    pub(crate) fn track(&mut self, start: bool, timestamp: Duration, stats: &ScanStats) {
+        if start {
+            if let Some(last) = self.last_start {
+                let period = timestamp.saturating_sub(last);
+                if !period.is_zero() {
+                    let rate = self.samples as f64 / period.as_secs_f64();
+                    let expected = self.timing.samples_per_second();
+                    stats.set_rates(
+                        1.0 / period.as_secs_f64(),
+                        rate,
+                        expected > 0.0 && rate < expected * LAG_RATIO,
+                    );
+                }
+            }
+            self.last_start = Some(timestamp);
+            self.samples = 0;
+        }
+        self.samples += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_rates_from_the_sample_duration() {
+        let timing = ScanTiming::new(Framing::Node, Duration::from_micros(250));
+        assert_eq!(timing.samples_per_second(), 4000.0);
+        assert_eq!(timing.bytes_per_second(), 20000.0);
+        assert_eq!(timing.expected_samples(Duration::from_millis(100)), 400.0);
+
+        let timing = ScanTiming::new(Framing::DenseCapsule, Duration::from_micros(125));
+        assert_eq!(timing.bytes_per_second(), 8000.0 * 84.0 / 40.0);
+    }
+
+    #[test]
+    fn reads_whole_packets_within_limits() {
+        // 100 bytes every 5 ms
+        assert_eq!(ScanTiming::new(Framing::Node, Duration::from_micros(250)).read_size(), 100);
+        // one capsule every 5 ms
+        assert_eq!(ScanTiming::new(Framing::DenseCapsule, Duration::from_micros(125)).read_size(), 84);
+        assert_eq!(ScanTiming::new(Framing::Node, Duration::from_micros(1)).read_size(), MAX_READ);
+        // an unknown rate reads little at a time
+        assert_eq!(ScanTiming::new(Framing::Node, Duration::ZERO).read_size(), MIN_READ);
+    }
+
+    #[test]
+    fn tracks_rotation_and_lag() {
+        let timing = ScanTiming::new(Framing::Node, Duration::from_micros(250));
+        let stats = ScanStats::default();
+        let mut tracker = RateTracker::new(timing);
+
+        // one revolution of 400 samples in 100 ms keeps up
+        for i in 0..400u32 {
+            tracker.track(i == 0, Duration::from_micros(250) * i, &stats);
+        }
+        tracker.track(true, Duration::from_millis(100), &stats);
+        assert_eq!(stats.rotation_hz(), 10.0);
+        assert_eq!(stats.sample_rate(), 4000.0);
+        assert!(!stats.is_lagging());
+
+        // the next one only delivers 300
+        for i in 1..300u32 {
+            tracker.track(false, Duration::from_millis(100) + Duration::from_micros(250) * i, &stats);
+        }
+        tracker.track(true, Duration::from_millis(200), &stats);
+        assert_eq!(stats.sample_rate(), 3000.0);
+        assert!(stats.is_lagging());
+    }
+}
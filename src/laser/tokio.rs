@@ -0,0 +1,403 @@
+//! Async lidar driver for tokio applications
+//!
+//! Mirrors the blocking [`crate::laser::Lidar`], with every request being awaitable and scan
+//! data delivered as a [`Stream`] instead of through a reader thread.
+
+use crate::error::RxError;
+use crate::laser::cmd::ConfEntry::{Count, MacAddr, Name, StaticIpAddr, Typical, UsPerSample};
+use crate::laser::cmd::SlLidarAnsType::{DevHealth, DevInfo, SampleRate};
+use crate::laser::cmd::SlLidarCmd::{GetDeviceHealth, GetDeviceInfo, GetSampleRate, Reset, Scan, SetLidarConf, Stop};
+use crate::laser::cmd::{
+    LidarConf, ConfEntry, SlLidarAnsType, SlLidarIpConfT, SlLidarResponseDeviceHealthT,
+    SlLidarResponseDeviceInfoT, SlLidarResponseDeviceMacaddrInfoT, SlLidarResponseGetLidarConf,
+    SlLidarResponseSampleRateT,
+};
+use crate::laser::clock::{Clock, MonotonicClock, Stamper};
+use crate::laser::framer::{Framer, Framing};
+use crate::laser::lidar::S1_BAUD;
+use crate::laser::protocol;
+use crate::laser::timing::ScanTiming;
+use crate::laser::lidar::ScanMode;
+use crate::laser::protocol::{Response, ResponseDescriptor, Sample, SCAN_DESCRIPTOR};
+use crate::laser::session::{QUIET, STOP_DEADLINE};
+use futures::stream::{self, Stream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::time::timeout;
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+/// How long a read may wait for the lidar before failing
+const TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Represents an async connection to a lidar
+pub struct Lidar<T = SerialStream> {
+    /// serial or network connection object
+    transport: T,
+    /// source of sample timestamps
+    clock: Arc<dyn Clock>,
+}
+
+impl Lidar<SerialStream> {
+    /// initializes a serial connection to the lidar on the given port.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn init(port: String) -> Result<Lidar<SerialStream>, serialport::Error> {
+        tokio_serial::new(&port, S1_BAUD as u32)
+            .open_native_async()
+            .map(Lidar::with_channel)
+    }
+}
+
+impl Lidar<TcpStream> {
+    /// initializes a TCP connection to a network lidar.
+    pub async fn connect_tcp(addr: impl ToSocketAddrs) -> Result<Lidar<TcpStream>, RxError> {
+        let stream = timeout(TIMEOUT, TcpStream::connect(addr))
+            .await
+            .map_err(|_| RxError::TimedOut)??;
+        stream.set_nodelay(true)?;
+        Ok(Lidar::with_channel(stream))
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> Lidar<T> {
+    /// Drives a lidar over an already established async byte stream
+    pub fn with_channel(transport: T) -> Lidar<T> {
+        Lidar {
+            transport,
+            clock: Arc::new(MonotonicClock),
+        }
+    }
+
+    /// Replaces the clock timestamping samples of subsequent scans
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Fills `buf`, failing if the lidar goes quiet for too long
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), RxError> {
+        timeout(TIMEOUT, self.transport.read_exact(buf))
+            .await
+            .map_err(|_| RxError::TimedOut)??;
+        Ok(())
+    }
+
+    /// Performs a request with a single response
+    async fn single_req(&mut self, req: &[u8]) -> Result<Response, RxError> {
+        self.transport.write_all(req).await?;
+        // response header
+        let mut descriptor_bytes = [0u8; 7];
+        self.read_exact(&mut descriptor_bytes).await?;
+
+        let descriptor = ResponseDescriptor::parse(descriptor_bytes)?;
+
+        // data
+        let mut data = vec![0u8; descriptor.len as usize];
+        self.read_exact(&mut data).await?;
+
+        Ok(Response { descriptor, data })
+    }
+
+    /// stops the lidar
+    ///
+    /// Sends Stop (or Reset) and waits for in-flight data to drain until the port is quiet, so
+    /// that the next request is not answered with leftover scan data.
+    pub async fn stop(&mut self, reset: bool) -> Result<(), RxError> {
+        let sent = self
+            .transport
+            .write_all(&[0xa5, (if reset { Reset } else { Stop }) as u8])
+            .await;
+        let drained = self.drain().await;
+
+        sent?;
+        drained
+    }
+
+    /// Discards incoming bytes until the lidar stays silent for `QUIET`
+    async fn drain(&mut self) -> Result<(), RxError> {
+        let deadline = Instant::now() + STOP_DEADLINE;
+        let mut data = [0u8; 4096];
+
+        loop {
+            match timeout(QUIET, self.transport.read(&mut data)).await {
+                Err(_) | Ok(Ok(0)) => return Ok(()),
+                Ok(Ok(_)) if Instant::now() < deadline => continue,
+                Ok(Ok(_)) => return Err(RxError::TimedOut),
+                Ok(Err(err)) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Resets/reboots the lidar
+    pub async fn reset(&mut self) -> Result<(), RxError> {
+        self.stop(true).await
+    }
+
+    /// Retrieves device information
+    pub async fn get_info(&mut self) -> Result<SlLidarResponseDeviceInfoT, RxError> {
+        let res = self.single_req(&[0xa5, GetDeviceInfo as u8]).await?.expect(DevInfo, 20)?;
+        Ok(SlLidarResponseDeviceInfoT::from_bytes(&res.data))
+    }
+
+    /// Retrieves the lidar's health
+    pub async fn get_health(&mut self) -> Result<SlLidarResponseDeviceHealthT, RxError> {
+        let res = self.single_req(&[0xa5, GetDeviceHealth as u8]).await?.expect(DevHealth, 3)?;
+        Ok(SlLidarResponseDeviceHealthT::from_bytes(&res.data))
+    }
+
+    /// Returns the sampling rate of the lidar
+    pub async fn get_sample_rate(&mut self) -> Result<SlLidarResponseSampleRateT, RxError> {
+        let res = self.single_req(&[0xa5, GetSampleRate as u8]).await?.expect(SampleRate, 4)?;
+        Ok(SlLidarResponseSampleRateT::from_bytes(&res.data))
+    }
+
+    /// Queries the lidar for specific configuration settings
+    pub async fn get_lidar_conf(
+        &mut self,
+        entry: ConfEntry,
+        payload: Option<u16>,
+    ) -> Result<SlLidarResponseGetLidarConf, RxError> {
+        let res = self.single_req(&protocol::conf_req(entry, payload)?).await?.expect(SlLidarAnsType::GetLidarConf, 4)?;
+        Ok(SlLidarResponseGetLidarConf::from_bytes(&res.data))
+    }
+
+    /// Returns the number of entries in the lidar's scan mode table
+    pub async fn get_scan_mode_count(&mut self) -> Result<u16, RxError> {
+        let conf = self.get_lidar_conf(Count, None).await?.expect_len(2)?;
+        Ok(conf.payload_u16())
+    }
+
+    /// Returns the id of the mode the lidar recommends
+    pub async fn get_typical_scan_mode(&mut self) -> Result<u16, RxError> {
+        let conf = self.get_lidar_conf(Typical, None).await?.expect_len(2)?;
+        Ok(conf.payload_u16())
+    }
+
+    /// Returns the name of the given scan mode
+    pub async fn get_scan_mode_name(&mut self, mode: u16) -> Result<String, RxError> {
+        Ok(self.get_lidar_conf(Name, Some(mode)).await?.payload_str())
+    }
+
+    /// Looks up the id of a scan mode in the lidar's mode table, by name.
+    ///
+    /// A dense scan falls back to the typical mode if no mode is named like it, as lidars
+    /// streaming dense capsules recommend them.
+    pub async fn scan_mode_id(&mut self, mode: ScanMode) -> Result<u16, RxError> {
+        for id in 0..self.get_scan_mode_count().await? {
+            if mode.matches(&self.get_scan_mode_name(id).await?) {
+                return Ok(id);
+            }
+        }
+
+        match mode {
+            ScanMode::Standard => Err(RxError::UnknownScanMode),
+            ScanMode::Dense => self.get_typical_scan_mode().await,
+        }
+    }
+
+    /// Returns how long one measurement takes in the given scan mode
+    pub async fn get_us_per_sample(&mut self, mode: u16) -> Result<Duration, RxError> {
+        let conf = self.get_lidar_conf(UsPerSample, Some(mode)).await?.expect_len(4)?;
+        let us_q8 = u32::from_le_bytes(conf.payload[..4].try_into().unwrap());
+
+        Ok(Duration::from_nanos(us_q8 as u64 * 1000 / (1 << 8)))
+    }
+
+    /// Writes a configuration entry to the lidar
+    pub async fn set_lidar_conf(&mut self, conf: LidarConf) -> Result<(), RxError> {
+        let res = self
+            .single_req(&protocol::payload_req(SetLidarConf, &conf.payload()))
+            .await?;
+        protocol::check_set_conf(&conf, &res)
+    }
+
+    /// Retrieves the MAC address of a network lidar
+    pub async fn get_mac_addr(&mut self) -> Result<SlLidarResponseDeviceMacaddrInfoT, RxError> {
+        let conf = self.get_lidar_conf(MacAddr, None).await?.expect_len(6)?;
+        Ok(SlLidarResponseDeviceMacaddrInfoT::from_bytes(&conf.payload))
+    }
+
+    /// Retrieves the static IP configuration of a network lidar
+    pub async fn get_ip_conf(&mut self) -> Result<SlLidarIpConfT, RxError> {
+        let conf = self.get_lidar_conf(StaticIpAddr, None).await?.expect_len(12)?;
+        Ok(SlLidarIpConfT::from_bytes(&conf.payload))
+    }
+
+    /// Changes the static IP configuration of a network lidar
+    pub async fn set_ip_conf(&mut self, conf: SlLidarIpConfT) -> Result<(), RxError> {
+        self.set_lidar_conf(LidarConf::StaticIpAddr(conf)).await
+    }
+
+    /// Requests transmission of laser data from the lidar
+    ///
+    /// The returned stream borrows the lidar; drop it and call [`Lidar::stop`] to end the scan.
+    /// Samples before the first start of a revolution are skipped.
+    pub async fn start_scan(
+        &mut self,
+    ) -> Result<impl Stream<Item = Result<Sample, RxError>> + '_, RxError> {
+        // firmware predating the scan mode table only answers GetSampleRate
+        let sample_duration = match self.scan_mode_id(ScanMode::Standard).await {
+            Ok(id) => self.get_us_per_sample(id).await.ok(),
+            Err(_) => None,
+        };
+        let sample_duration = match sample_duration {
+            Some(duration) => duration,
+            None => Duration::from_micros(self.get_sample_rate().await?.std_sample_duration_us as u64),
+        };
+        let stamper = Stamper::new(Arc::clone(&self.clock), sample_duration);
+        let timing = ScanTiming::new(Framing::Node, sample_duration);
+
+        // signal lidar to begin a scan
+        self.transport.write_all(&[0xa5, Scan as u8]).await?;
+
+        let mut descriptor = [0u8; 7];
+        self.read_exact(&mut descriptor).await?;
+        if descriptor != SCAN_DESCRIPTOR {
+            return Err(RxError::Corrupted(descriptor));
+        }
+
+        let state = StreamState {
+            lidar: self,
+            framer: Framer::new(Framing::Node),
+            stamper,
+            chunk: vec![0u8; timing.read_size()],
+            seeking: true,
+            received: Duration::ZERO,
+        };
+        Ok(stream::try_unfold(state, |mut state| async move {
+            loop {
+                while let Some(packet) = state.framer.next_packet() {
+                    let Some(mut sample) = Sample::from_node(packet) else {
+                        continue;
+                    };
+
+                    if state.seeking && !sample.start {
+                        continue;
+                    }
+
+                    state.seeking = false;
+                    let later = state.framer.buffered();
+                    state.stamper.stamp(&mut sample, state.received, later);
+                    return Ok(Some((sample, state)));
+                }
+
+                let len = timeout(TIMEOUT, state.lidar.transport.read(&mut state.chunk))
+                    .await
+                    .map_err(|_| RxError::TimedOut)??;
+                if len == 0 {
+                    return Ok(None);
+                }
+                state.received = state.stamper.now();
+                state.framer.push(&state.chunk[..len]);
+            }
+        }))
+    }
+}
+
+/// What a scan stream carries between samples
+struct StreamState<'a, T> {
+    lidar: &'a mut Lidar<T>,
+    framer: Framer,
+    stamper: Stamper,
+    /// read buffer, sized to the expected data rate
+    chunk: Vec<u8>,
+    /// skipping samples until the first start of a revolution
+    seeking: bool,
+    /// time the buffered data was read
+    received: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, DuplexStream};
+
+    /// Descriptor and data of a GetDeviceInfo answer
+    fn info_response() -> Vec<u8> {
+        let mut res = vec![0xa5, 0x5a, 0x14, 0x00, 0x00, 0x00, 0x04];
+        res.extend_from_slice(&[0x61, 0x1d, 0x01, 0x12]);
+        res.extend(0..16);
+        res
+    }
+
+    /// Descriptor and data of a GetLidarConf answer for `entry`
+    fn conf_response(entry: ConfEntry, payload: &[u8]) -> Vec<u8> {
+        let mut res = vec![0xa5, 0x5a, 4 + payload.len() as u8, 0x00, 0x00, 0x00, 0x20];
+        res.extend_from_slice(&(entry as u32).to_le_bytes());
+        res.extend_from_slice(payload);
+        res
+    }
+
+    /// Reads a request of `len` bytes on the device side
+    async fn request(device: &mut DuplexStream, len: usize) -> Vec<u8> {
+        let mut req = vec![0u8; len];
+        device.read_exact(&mut req).await.unwrap();
+        req
+    }
+
+    #[tokio::test]
+    async fn answers_requests() {
+        let (stream, mut device) = duplex(256);
+        let mut lidar = Lidar::with_channel(stream);
+
+        let mac = [0x00, 0x1c, 0x42, 0x0a, 0x0b, 0x0c];
+        let device = tokio::spawn(async move {
+            assert_eq!(request(&mut device, 2).await, [0xa5, GetDeviceInfo as u8]);
+            device.write_all(&info_response()).await.unwrap();
+
+            let req = protocol::conf_req(MacAddr, None).unwrap();
+            assert_eq!(request(&mut device, req.len()).await, req);
+            device.write_all(&conf_response(MacAddr, &mac)).await.unwrap();
+        });
+
+        let info = lidar.get_info().await.unwrap();
+        assert_eq!(info.model, 0x61);
+        assert_eq!(info.firmware_version, 0x011d);
+        assert_eq!(lidar.get_mac_addr().await.unwrap().macaddr, mac);
+        device.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_short_conf_payloads() {
+        let (stream, mut device) = duplex(256);
+        let mut lidar = Lidar::with_channel(stream);
+
+        let device = tokio::spawn(async move {
+            // a lidar without network interface answers with an empty entry
+            let req = protocol::conf_req(MacAddr, None).unwrap();
+            request(&mut device, req.len()).await;
+            device.write_all(&conf_response(MacAddr, &[])).await.unwrap();
+
+            let req = protocol::conf_req(StaticIpAddr, None).unwrap();
+            request(&mut device, req.len()).await;
+            device.write_all(&conf_response(StaticIpAddr, &[192, 168, 11, 2])).await.unwrap();
+        });
+
+        assert!(matches!(lidar.get_mac_addr().await, Err(RxError::UnexpectedResponse(0x20))));
+        assert!(matches!(lidar.get_ip_conf().await, Err(RxError::UnexpectedResponse(0x20))));
+        device.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stop_drains_scan_data() {
+        let (stream, mut device) = duplex(4096);
+        let mut lidar = Lidar::with_channel(stream);
+
+        let device = tokio::spawn(async move {
+            assert_eq!(request(&mut device, 2).await, [0xa5, Stop as u8]);
+            // measurements already on their way when Stop arrived
+            for _ in 0..3 {
+                device.write_all(&[0x3e; 100]).await.unwrap();
+                tokio::time::sleep(QUIET / 5).await;
+            }
+
+            assert_eq!(request(&mut device, 2).await, [0xa5, GetDeviceInfo as u8]);
+            device.write_all(&info_response()).await.unwrap();
+        });
+
+        lidar.stop(false).await.unwrap();
+        assert_eq!(lidar.get_info().await.unwrap().model, 0x61);
+        device.await.unwrap();
+    }
+}
@@ -1,5 +1,10 @@
 #[cfg(feature = "examples")]
 pub mod examples;
 pub mod laser;
+pub mod render;
+pub mod ros;
+pub mod scan;
+#[cfg(feature = "tui")]
+pub mod tui;
 mod util;
 pub mod error;
\ No newline at end of file
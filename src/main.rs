@@ -1,15 +1,43 @@
-use rangefinder::laser::Lidar;
+use rangefinder::laser::{discovery, Lidar};
 use std::error::Error;
 
 // #[show_image::main]
 fn main() -> Result<(), Box<dyn Error>> {
-    // initialize lidar
-    let mut lidar = Lidar::init(String::from("COM3")).expect("Lidar should have initialized");
+    // initialize lidar on the given port, or the first one found
+    #[cfg_attr(not(any(feature = "examples", feature = "foxglove", feature = "tui")), allow(unused))]
+    let mut lidar = match std::env::args().nth(1) {
+        Some(port) => Lidar::init(port)?,
+        None => {
+            let detected = discovery::discover()?.into_iter().next().ok_or("No lidar found")?;
+            println!(
+                "Found {} (serial {}) on {}",
+                detected.info.model_name(),
+                detected.info.serial_number_hex(),
+                detected.port
+            );
+            detected.open()?
+        }
+    };
 
     #[cfg(feature = "examples")] {
         rangefinder::examples::print_modes(&mut lidar)?;
     }
 
+    #[cfg(feature = "foxglove")] {
+        use rangefinder::ros::foxglove::{serve_lidar, FoxgloveServer, ServeConfig, DEFAULT_PORT};
+
+        let server = FoxgloveServer::bind(("127.0.0.1", DEFAULT_PORT))?;
+        println!("Serving Foxglove WebSocket on ws://{}", server.local_addr());
+        let served = serve_lidar(&server, &mut lidar, &ServeConfig::default());
+        for err in server.take_errors() {
+            eprintln!("Foxglove client error: {}", err);
+        }
+        served?;
+    }
+
+    #[cfg(feature = "tui")] {
+        rangefinder::tui::run(&mut lidar)?;
+    }
+
     Ok(())
 }
-
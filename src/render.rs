@@ -0,0 +1,295 @@
+//! Headless rendering of scans to images, PNG snapshots and animated GIFs
+//!
+//! Encoding to files requires the `render` feature.
+
+use crate::laser::{Revolution, Sample};
+use crate::scan::geometry::wrap_pi;
+use crate::scan::{Mounting, Point2};
+use std::f64::consts::TAU;
+#[cfg(feature = "render")]
+use std::fs::File;
+#[cfg(feature = "render")]
+use std::io::{self, BufWriter, Write};
+#[cfg(feature = "render")]
+use std::path::Path;
+#[cfg(feature = "render")]
+use std::time::Duration;
+
+/// Colour of the weakest returns
+pub const RAMP_LOW: u32 = 0x1e4160;
+/// Colour of the strongest returns
+pub const RAMP_HIGH: u32 = 0xf3ff82;
+/// Intensity at which the ramp reaches [`RAMP_HIGH`]
+const RAMP_SPAN: f32 = 64.0;
+
+fn rgb(colour: u32) -> [u8; 3] {
+    [(colour >> 16) as u8, (colour >> 8) as u8, colour as u8]
+}
+
+/// Colour of a return of the given intensity, blending from [`RAMP_LOW`] to [`RAMP_HIGH`]
+pub fn ramp(intensity: u8) -> [u8; 3] {
+    let t = (intensity as f32 / RAMP_SPAN).min(1.0);
+    let (low, high) = (rgb(RAMP_LOW), rgb(RAMP_HIGH));
+    let mut colour = [0; 3];
+    for i in 0..3 {
+        colour[i] = (low[i] as f32 + (high[i] as f32 - low[i] as f32) * t).round() as u8;
+    }
+    colour
+}
+
+/// Colour of a sample's return, the top of the ramp if its intensity is unknown (dense scans)
+pub fn colour(sample: &Sample) -> [u8; 3] {
+    ramp(sample.intensity.unwrap_or(u8::MAX))
+}
+
+/// An 8-bit RGB image, rows top to bottom
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, background: [u8; 3]) -> Image {
+        Image {
+            width,
+            height,
+            pixels: background.repeat(width as usize * height as usize),
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 3]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let pos = (y as usize * self.width as usize + x as usize) * 3;
+        Some([self.pixels[pos], self.pixels[pos + 1], self.pixels[pos + 2]])
+    }
+
+    /// Sets a pixel, ignoring coordinates outside the image
+    pub fn put(&mut self, x: i64, y: i64, colour: [u8; 3]) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let pos = (y as usize * self.width as usize + x as usize) * 3;
+        self.pixels[pos..pos + 3].copy_from_slice(&colour);
+    }
+
+    pub fn fill(&mut self, colour: [u8; 3]) {
+        for pixel in self.pixels.chunks_exact_mut(3) {
+            pixel.copy_from_slice(&colour);
+        }
+    }
+
+    #[cfg(feature = "render")]
+    pub fn write_png<W: Write>(&self, out: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(out, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "render")]
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+}
+
+/// Draws scans as seen from above, with the robot's forward axis pointing right
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Renderer {
+    pub width: u32,
+    pub height: u32,
+    /// metres covered by a pixel
+    pub scale: f64,
+    /// where the lidar sits on the robot
+    pub mounting: Mounting,
+    /// base frame point shown at the image centre, in metres
+    pub centre: Point2,
+    pub background: [u8; 3],
+    /// side of the square drawn per point, in pixels
+    pub point_size: u32,
+}
+
+impl Default for Renderer {
+    /// 1920×1080 at 5 mm per pixel, as the live view
+    fn default() -> Renderer {
+        Renderer::new(1920, 1080, 0.005)
+    }
+}
+
+impl Renderer {
+    pub fn new(width: u32, height: u32, scale: f64) -> Renderer {
+        Renderer {
+            width,
+            height,
+            scale,
+            mounting: Mounting::default(),
+            centre: Point2::new(0.0, 0.0),
+            background: [0; 3],
+            point_size: 1,
+        }
+    }
+
+    /// Scale fitting `range` metres around the centre into the image
+    pub fn fit(width: u32, height: u32, range: f64) -> Renderer {
+        Renderer::new(width, height, 2.0 * range / width.min(height).max(1) as f64)
+    }
+
+    /// Pixel a base frame point lands on, which may lie outside the image
+    pub fn to_pixel(&self, point: Point2) -> (i64, i64) {
+        let x = ((point.x - self.centre.x) / self.scale).floor() as i64 + self.width as i64 / 2;
+        let y = ((self.centre.y - point.y) / self.scale).floor() as i64 + self.height as i64 / 2;
+        (x, y)
+    }
+
+    /// Pixel a sample lands on, which may lie outside the image
+    pub fn project(&self, sample: &Sample) -> (i64, i64) {
+        self.to_pixel(self.mounting.to_base(sample))
+    }
+
+    /// A blank image of the renderer's size
+    pub fn blank(&self) -> Image {
+        Image::new(self.width, self.height, self.background)
+    }
+
+    /// Draws a single sample in the given colour
+    pub fn plot(&self, image: &mut Image, sample: &Sample, colour: [u8; 3]) {
+        let size = self.point_size.max(1) as i64;
+        let (x, y) = self.project(sample);
+        for dy in 0..size {
+            for dx in 0..size {
+                image.put(x - size / 2 + dx, y - size / 2 + dy, colour);
+            }
+        }
+    }
+
+    /// Draws valid samples over an image, e.g. to accumulate several revolutions
+    pub fn draw<'a>(&self, image: &mut Image, samples: impl IntoIterator<Item = &'a Sample>) {
+        for sample in samples.into_iter().filter(|s| s.is_valid()) {
+            self.plot(image, sample, colour(sample));
+        }
+    }
+
+    /// Draws range rings every `spacing` metres around the lidar and the robot's axes through it
+    pub fn draw_grid(&self, image: &mut Image, spacing: f64, colour: [u8; 3]) {
+        let origin = Point2::new(self.mounting.x, self.mounting.y);
+        let (ox, oy) = self.to_pixel(origin);
+        for x in 0..self.width as i64 {
+            image.put(x, oy, colour);
+        }
+        for y in 0..self.height as i64 {
+            image.put(ox, y, colour);
+        }
+
+        if spacing <= 0.0 {
+            return;
+        }
+        // rings beyond the farthest corner can't be seen
+        let corners = [(0, 0), (self.width as i64, 0), (0, self.height as i64), (self.width as i64, self.height as i64)];
+        let farthest = corners
+            .iter()
+            .map(|&(x, y)| (((x - ox).pow(2) + (y - oy).pow(2)) as f64).sqrt())
+            .fold(0.0, f64::max)
+            * self.scale;
+
+        // nor rings that stay short of the image
+        let nearest_x = ox.clamp(0, self.width as i64 - 1) - ox;
+        let nearest_y = oy.clamp(0, self.height as i64 - 1) - oy;
+        let nearest = ((nearest_x.pow(2) + nearest_y.pow(2)) as f64).sqrt() * self.scale;
+
+        // only the arc facing the image needs drawing when the lidar is off-screen
+        let (start, span) = if nearest == 0.0 {
+            (0.0, TAU)
+        } else {
+            let angle = |x: i64, y: i64| ((oy - y) as f64).atan2((x - ox) as f64);
+            let middle = angle(self.width as i64 / 2, self.height as i64 / 2);
+            let offsets = corners.map(|(x, y)| wrap_pi(angle(x, y) - middle));
+            let low = offsets.iter().copied().fold(0.0, f64::min);
+            let high = offsets.iter().copied().fold(0.0, f64::max);
+            (middle + low, high - low)
+        };
+
+        let mut radius = (nearest / spacing).floor().max(1.0) * spacing;
+        while radius <= farthest {
+            // about one step per pixel of arc
+            let steps = (span * radius / self.scale).ceil().max(8.0) as usize;
+            for i in 0..=steps {
+                let angle = start + span * i as f64 / steps as f64;
+                let point = Point2::new(origin.x + radius * angle.cos(), origin.y + radius * angle.sin());
+                let (x, y) = self.to_pixel(point);
+                image.put(x, y, colour);
+            }
+            radius += spacing;
+        }
+    }
+
+    /// Draws a revolution on a blank image
+    pub fn render(&self, revolution: &Revolution) -> Image {
+        let mut image = self.blank();
+        self.draw(&mut image, &revolution.samples);
+        image
+    }
+}
+
+#[cfg(feature = "render")]
+fn gif_error(err: gif::EncodingError) -> io::Error {
+    match err {
+        gif::EncodingError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+/// Writes rendered frames as a looping animated GIF
+#[cfg(feature = "render")]
+pub struct GifWriter<W: Write> {
+    encoder: gif::Encoder<W>,
+    width: u16,
+    height: u16,
+    /// frame delay in hundredths of a second
+    delay: u16,
+}
+
+#[cfg(feature = "render")]
+impl GifWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, width: u32, height: u32, frame_time: Duration) -> io::Result<Self> {
+        GifWriter::new(BufWriter::new(File::create(path)?), width, height, frame_time)
+    }
+}
+
+#[cfg(feature = "render")]
+impl<W: Write> GifWriter<W> {
+    /// Starts a GIF of the given size showing each frame for `frame_time` (10 ms resolution)
+    pub fn new(out: W, width: u32, height: u32, frame_time: Duration) -> io::Result<GifWriter<W>> {
+        let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "GIFs are at most 65535 pixels wide and high"));
+        };
+        let mut encoder = gif::Encoder::new(out, width, height, &[]).map_err(gif_error)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(gif_error)?;
+        Ok(GifWriter {
+            encoder,
+            width,
+            height,
+            delay: (frame_time.as_millis() / 10).clamp(1, u16::MAX as u128) as u16,
+        })
+    }
+
+    /// Appends a frame, which must have the size the GIF was started with
+    pub fn push(&mut self, image: &Image) -> io::Result<()> {
+        if image.width != self.width as u32 || image.height != self.height as u32 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame size differs from the GIF's"));
+        }
+        let mut frame = gif::Frame::from_rgb_speed(self.width, self.height, &image.pixels, 10);
+        frame.delay = self.delay;
+        self.encoder.write_frame(&frame).map_err(gif_error)
+    }
+
+    /// Writes the trailer, returning the underlying writer
+    pub fn finish(self) -> io::Result<W> {
+        self.encoder.into_inner()
+    }
+}
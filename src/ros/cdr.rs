@@ -0,0 +1,125 @@
+/// Encapsulation header of little endian plain CDR, as used by ROS 2
+const CDR_LE: [u8; 4] = [0x00, 0x01, 0x00, 0x00];
+
+/// Serializes values in little endian CDR, aligning each primitive to its size
+#[derive(Debug, Default)]
+pub struct CdrWriter {
+    buf: Vec<u8>,
+}
+
+impl CdrWriter {
+    pub fn new() -> CdrWriter {
+        CdrWriter::default()
+    }
+
+    /// The serialized bytes, without encapsulation header
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    fn align(&mut self, n: usize) {
+        let padding = (n - self.buf.len() % n) % n;
+        self.buf.resize(self.buf.len() + padding, 0);
+    }
+
+    pub fn write_u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn write_bool(&mut self, v: bool) {
+        self.write_u8(v as u8);
+    }
+
+    pub fn write_u32(&mut self, v: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn write_i32(&mut self, v: i32) {
+        self.align(4);
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, v: f32) {
+        self.align(4);
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    /// Length including the terminating NUL, then the bytes
+    pub fn write_string(&mut self, v: &str) {
+        self.write_u32(v.len() as u32 + 1);
+        self.buf.extend_from_slice(v.as_bytes());
+        self.buf.push(0);
+    }
+
+    pub fn write_bytes(&mut self, v: &[u8]) {
+        self.write_u32(v.len() as u32);
+        self.buf.extend_from_slice(v);
+    }
+
+    pub fn write_f32_seq(&mut self, v: &[f32]) {
+        self.write_u32(v.len() as u32);
+        for &x in v {
+            self.write_f32(x);
+        }
+    }
+
+    pub fn write_seq<T: CdrSerialize>(&mut self, v: &[T]) {
+        self.write_u32(v.len() as u32);
+        for x in v {
+            x.serialize(self);
+        }
+    }
+}
+
+/// A message that can be written as CDR
+pub trait CdrSerialize {
+    fn serialize(&self, w: &mut CdrWriter);
+}
+
+/// Serializes a message with its encapsulation header, ready to publish or record
+pub fn to_cdr<T: CdrSerialize>(msg: &T) -> Vec<u8> {
+    let mut w = CdrWriter::new();
+    msg.serialize(&mut w);
+
+    let mut out = CDR_LE.to_vec();
+    out.extend(w.into_inner());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ros::msg::{Header, LaserScan, Time};
+
+    #[test]
+    fn aligns_primitives_to_their_size() {
+        let mut w = CdrWriter::new();
+        w.write_u8(1);
+        w.write_u32(2);
+        w.write_bool(true);
+        w.write_f32(1.0);
+        assert_eq!(w.into_inner(), [1, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0x00, 0x00, 0x80, 0x3f]);
+    }
+
+    #[test]
+    fn encodes_laser_scans() {
+        let scan = LaserScan {
+            header: Header {
+                stamp: Time { sec: 1, nanosec: 2 },
+                frame_id: "laser".to_string(),
+            },
+            ranges: vec![1.5],
+            ..LaserScan::default()
+        };
+
+        let mut expected = CDR_LE.to_vec();
+        expected.extend_from_slice(&[1, 0, 0, 0, 2, 0, 0, 0]);
+        // the NUL terminated frame id, padded up to the next float
+        expected.extend_from_slice(&[6, 0, 0, 0, b'l', b'a', b's', b'e', b'r', 0, 0, 0]);
+        expected.extend_from_slice(&[0; 7 * 4]);
+        expected.extend_from_slice(&[1, 0, 0, 0, 0x00, 0x00, 0xc0, 0x3f]);
+        expected.extend_from_slice(&[0, 0, 0, 0]);
+        assert_eq!(to_cdr(&scan), expected);
+    }
+}
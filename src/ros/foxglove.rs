@@ -0,0 +1,363 @@
+//! Live visualization over the Foxglove WebSocket protocol
+//!
+//! Open Foxglove (app or browser), choose "Foxglove WebSocket" and connect to the server's
+//! address, e.g. `ws://localhost:8765`.
+
+use crate::laser::{Lidar, Revolution, ScanMode};
+use crate::ros::cdr::{to_cdr, CdrSerialize};
+use crate::ros::msg::{LaserScan, PointCloud2, RosMessage};
+use crate::scan::{Mounting, Resampler};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tungstenite::handshake::server::{Request, Response};
+use tungstenite::error::ProtocolError;
+use tungstenite::{Message, WebSocket};
+
+/// Default port of Foxglove WebSocket servers
+pub const DEFAULT_PORT: u16 = 8765;
+
+const SUBPROTOCOL: &str = "foxglove.websocket.v1";
+/// Opcode of binary message data frames
+const MESSAGE_DATA: u8 = 0x01;
+/// Messages queued per client before further ones are dropped
+const CLIENT_QUEUE: usize = 64;
+/// How often client threads check for outgoing messages while waiting for input
+const POLL: Duration = Duration::from_millis(20);
+/// Client errors kept until taken, older ones are dropped
+const MAX_ERRORS: usize = 16;
+
+/// Why a client connection failed
+pub type ClientError = Box<dyn Error + Send + Sync>;
+
+/// What a client thread sends to its client
+enum Outgoing {
+    Text(String),
+    Data {
+        channel: u16,
+        time: Duration,
+        payload: Arc<[u8]>,
+    },
+}
+
+struct Channel {
+    id: u16,
+    topic: String,
+    schema_name: &'static str,
+    schema: &'static str,
+}
+
+impl Channel {
+    fn advertisement(&self) -> Value {
+        json!({
+            "id": self.id,
+            "topic": self.topic,
+            "encoding": "cdr",
+            "schemaName": self.schema_name,
+            "schema": self.schema,
+            "schemaEncoding": "ros2msg",
+        })
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    channels: Mutex<Vec<Channel>>,
+    clients: Mutex<Vec<SyncSender<Outgoing>>>,
+    errors: Mutex<VecDeque<ClientError>>,
+    stop: AtomicBool,
+}
+
+impl Shared {
+    fn channels(&self) -> MutexGuard<'_, Vec<Channel>> {
+        self.channels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn clients(&self) -> MutexGuard<'_, Vec<SyncSender<Outgoing>>> {
+        self.clients.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn errors(&self) -> MutexGuard<'_, VecDeque<ClientError>> {
+        self.errors.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn add_error(&self, err: ClientError) {
+        let mut errors = self.errors();
+        if errors.len() == MAX_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(err);
+    }
+
+    /// Queues a message for every client, forgetting those that disconnected
+    fn broadcast(&self, make: impl Fn() -> Outgoing) {
+        self.clients().retain(|client| match client.try_send(make()) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
+/// Serves ROS 2 messages to Foxglove clients.
+///
+/// Clients are handled on their own threads; messages published while a client's queue is full
+/// are dropped for that client. Connections that fail are collected for [`FoxgloveServer::take_errors`].
+/// Dropping the server stops it.
+pub struct FoxgloveServer {
+    shared: Arc<Shared>,
+    addr: SocketAddr,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl FoxgloveServer {
+    /// Listens on `addr`, e.g. `("127.0.0.1", DEFAULT_PORT)`
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<FoxgloveServer> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let shared = Arc::new(Shared::default());
+
+        let acceptor_shared = Arc::clone(&shared);
+        let acceptor = thread::spawn(move || accept_clients(listener, acceptor_shared));
+
+        Ok(FoxgloveServer {
+            shared,
+            addr,
+            acceptor: Some(acceptor),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Number of connected clients
+    pub fn clients(&self) -> usize {
+        self.shared.clients().len()
+    }
+
+    /// Errors of client connections that failed since the last call, oldest first
+    pub fn take_errors(&self) -> Vec<ClientError> {
+        self.shared.errors().drain(..).collect()
+    }
+
+    /// Advertises a topic carrying messages of type `T`, returning its channel id
+    pub fn add_channel<T: RosMessage>(&self, topic: &str) -> u16 {
+        let mut channels = self.shared.channels();
+        let channel = Channel {
+            id: channels.len() as u16,
+            topic: topic.to_string(),
+            schema_name: T::NAME,
+            schema: T::DEFINITION,
+        };
+        let advertise = json!({ "op": "advertise", "channels": [channel.advertisement()] }).to_string();
+        let id = channel.id;
+        channels.push(channel);
+        drop(channels);
+
+        self.shared.broadcast(|| Outgoing::Text(advertise.clone()));
+        id
+    }
+
+    /// Sends a message to all clients subscribed to `channel`
+    pub fn publish<T: CdrSerialize>(&self, channel: u16, time: Duration, msg: &T) {
+        let payload: Arc<[u8]> = to_cdr(msg).into();
+        self.shared.broadcast(|| Outgoing::Data {
+            channel,
+            time,
+            payload: Arc::clone(&payload),
+        });
+    }
+
+    /// Resamples a revolution and publishes it as a `LaserScan` at the time of its first measurement
+    pub fn publish_revolution(&self, channel: u16, revolution: &Revolution, resampler: &Resampler, frame_id: &str) {
+        let scan = LaserScan::from_revolution(revolution, resampler, frame_id);
+        self.publish(channel, revolution.start_time().unwrap_or_default(), &scan);
+    }
+}
+
+impl Drop for FoxgloveServer {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        // wake the acceptor up so that it notices
+        let _ = TcpStream::connect(self.addr);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+    }
+}
+
+fn accept_clients(listener: TcpListener, shared: Arc<Shared>) {
+    for stream in listener.incoming() {
+        if shared.stop.load(Ordering::Relaxed) {
+            return;
+        }
+        let Ok(stream) = stream else {
+            continue;
+        };
+
+        let client_shared = Arc::clone(&shared);
+        thread::spawn(move || {
+            if let Err(err) = serve_client(stream, &client_shared) {
+                client_shared.add_error(err);
+            }
+        });
+    }
+}
+
+/// Handshakes with a client, then forwards its subscribed messages until it disconnects
+// the handshake callback's error type is dictated by tungstenite
+#[allow(clippy::result_large_err)]
+fn serve_client(stream: TcpStream, shared: &Shared) -> Result<(), ClientError> {
+    let mut ws = tungstenite::accept_hdr(stream, |_: &Request, mut response: Response| {
+        response
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", SUBPROTOCOL.parse().unwrap());
+        Ok(response)
+    })?;
+    ws.get_mut().set_read_timeout(Some(POLL))?;
+
+    let (tx, rx) = sync_channel(CLIENT_QUEUE);
+    {
+        // advertise under the lock, so that no channel added meanwhile is missed
+        let channels = shared.channels();
+        let info = json!({
+            "op": "serverInfo",
+            "name": concat!("rangefinder ", env!("CARGO_PKG_VERSION")),
+            "capabilities": [],
+        });
+        ws.send(Message::Text(info.to_string().into()))?;
+
+        let advertised: Vec<Value> = channels.iter().map(Channel::advertisement).collect();
+        if !advertised.is_empty() {
+            let advertise = json!({ "op": "advertise", "channels": advertised });
+            ws.send(Message::Text(advertise.to_string().into()))?;
+        }
+        shared.clients().push(tx);
+    }
+
+    client_loop(&mut ws, &rx, shared)
+}
+
+fn client_loop(ws: &mut WebSocket<TcpStream>, rx: &Receiver<Outgoing>, shared: &Shared) -> Result<(), ClientError> {
+    // subscription id -> channel id
+    let mut subscriptions: HashMap<u32, u16> = HashMap::new();
+
+    while !shared.stop.load(Ordering::Relaxed) {
+        loop {
+            match rx.try_recv() {
+                Ok(Outgoing::Text(text)) => ws.send(Message::Text(text.into()))?,
+                Ok(Outgoing::Data { channel, time, payload }) => {
+                    for (&subscription, _) in subscriptions.iter().filter(|(_, &c)| c == channel) {
+                        let mut frame = Vec::with_capacity(13 + payload.len());
+                        frame.push(MESSAGE_DATA);
+                        frame.extend_from_slice(&subscription.to_le_bytes());
+                        frame.extend_from_slice(&(time.as_nanos() as u64).to_le_bytes());
+                        frame.extend_from_slice(&payload);
+                        ws.send(Message::Binary(frame.into()))?;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+
+        let msg = match ws.read() {
+            Ok(msg) => msg,
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) =>
+            {
+                continue;
+            }
+            Err(
+                tungstenite::Error::ConnectionClosed
+                | tungstenite::Error::AlreadyClosed
+                | tungstenite::Error::Protocol(ProtocolError::ResetWithoutClosingHandshake),
+            ) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let Message::Text(text) = msg else {
+            continue;
+        };
+        let Ok(request) = serde_json::from_str::<Value>(text.as_str()) else {
+            continue;
+        };
+        match request["op"].as_str() {
+            Some("subscribe") => {
+                for sub in request["subscriptions"].as_array().into_iter().flatten() {
+                    if let (Some(id), Some(channel)) = (sub["id"].as_u64(), sub["channelId"].as_u64()) {
+                        subscriptions.insert(id as u32, channel as u16);
+                    }
+                }
+            }
+            Some("unsubscribe") => {
+                for id in request["subscriptionIds"].as_array().into_iter().flatten() {
+                    if let Some(id) = id.as_u64() {
+                        subscriptions.remove(&(id as u32));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// What [`serve_lidar`] scans and where it publishes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServeConfig {
+    pub mode: ScanMode,
+    /// topic of the resampled revolutions (`LaserScan`)
+    pub scan_topic: String,
+    /// topic of the revolutions' points (`PointCloud2`)
+    pub cloud_topic: String,
+    /// frame both are published in
+    pub frame_id: String,
+    /// beams per `LaserScan`
+    pub beams: usize,
+}
+
+impl Default for ServeConfig {
+    /// Standard scans as `/scan` and `/cloud` in frame `laser`, at half a degree per beam
+    fn default() -> Self {
+        ServeConfig {
+            mode: ScanMode::Standard,
+            scan_topic: "/scan".to_string(),
+            cloud_topic: "/cloud".to_string(),
+            frame_id: "laser".to_string(),
+            beams: 720,
+        }
+    }
+}
+
+/// Streams a lidar's scans to the server's clients until the scan ends
+pub fn serve_lidar(server: &FoxgloveServer, lidar: &mut Lidar, config: &ServeConfig) -> Result<(), Box<dyn Error>> {
+    let scan_channel = server.add_channel::<LaserScan>(&config.scan_topic);
+    let cloud_channel = server.add_channel::<PointCloud2>(&config.cloud_topic);
+
+    let model_range = lidar.get_info()?.max_distance();
+    let session = lidar.start_scan_mode(config.mode)?;
+    // without a reported or known range, the grid grows to the largest range seen so far
+    let range_max = session.max_distance().or(model_range);
+    let mut resampler = Resampler::full_circle(config.beams, range_max.unwrap_or(0.0));
+    for revolution in session.revolutions() {
+        if range_max.is_none() {
+            let largest = revolution.samples.iter().map(|s| s.distance() / 1000.0).fold(0.0, f32::max);
+            resampler.range_max = resampler.range_max.max(largest);
+        }
+        server.publish_revolution(scan_channel, &revolution, &resampler, &config.frame_id);
+        let cloud = PointCloud2::from_revolution(&revolution, &Mounting::default(), &config.frame_id);
+        server.publish(cloud_channel, revolution.start_time().unwrap_or_default(), &cloud);
+    }
+
+    Ok(session.error().map_or(Ok(()), Err)?)
+}
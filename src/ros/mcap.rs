@@ -0,0 +1,341 @@
+use crate::laser::Revolution;
+use crate::ros::cdr::{to_cdr, CdrSerialize};
+use crate::ros::msg::{LaserScan, RosMessage};
+use crate::scan::Resampler;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+const MAGIC: &[u8] = b"\x89MCAP0\r\n";
+
+/// Record opcodes
+const HEADER: u8 = 0x01;
+const FOOTER: u8 = 0x02;
+const SCHEMA: u8 = 0x03;
+const CHANNEL: u8 = 0x04;
+const MESSAGE: u8 = 0x05;
+const STATISTICS: u8 = 0x0b;
+const SUMMARY_OFFSET: u8 = 0x0e;
+const DATA_END: u8 = 0x0f;
+
+/// Builds the content of a record
+#[derive(Default)]
+struct Record(Vec<u8>);
+
+impl Record {
+    fn u8(mut self, v: u8) -> Self {
+        self.0.push(v);
+        self
+    }
+
+    fn u16(mut self, v: u16) -> Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn u32(mut self, v: u32) -> Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn u64(mut self, v: u64) -> Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    /// Length-prefixed bytes (strings, byte arrays and maps)
+    fn bytes(self, v: &[u8]) -> Self {
+        let mut record = self.u32(v.len() as u32);
+        record.0.extend_from_slice(v);
+        record
+    }
+
+    fn raw(mut self, v: &[u8]) -> Self {
+        self.0.extend_from_slice(v);
+        self
+    }
+}
+
+struct Channel {
+    id: u16,
+    schema_id: u16,
+    topic: String,
+    sequence: u32,
+    messages: u64,
+}
+
+/// Writes ROS 2 (CDR encoded) messages into an MCAP file, readable by Foxglove and
+/// `ros2 bag play`
+///
+/// Messages are written unchunked, followed by a summary with schemas, channels and statistics.
+/// Call [`McapWriter::finish`] to complete the file.
+///
+/// Log times are taken as nanoseconds since the UNIX epoch. Samples are stamped by the lidar's
+/// [`MonotonicClock`](crate::laser::MonotonicClock) unless told otherwise, whose origin is the
+/// start of the process, so recordings of revolutions would open in 1970. Record with
+/// `lidar.set_clock(Arc::new(SystemClock))` before starting the scan.
+pub struct McapWriter<W: Write> {
+    out: W,
+    /// bytes written so far
+    pos: u64,
+    /// name and definition by schema id
+    schemas: Vec<(&'static str, &'static str)>,
+    channels: Vec<Channel>,
+    /// log time range of all messages (ns)
+    start: u64,
+    end: u64,
+}
+
+impl McapWriter<BufWriter<File>> {
+    /// Creates an MCAP file at `path`
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        McapWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> McapWriter<W> {
+    /// Starts an MCAP stream with the `ros2` profile
+    pub fn new(out: W) -> io::Result<McapWriter<W>> {
+        let mut writer = McapWriter {
+            out,
+            pos: 0,
+            schemas: Vec::new(),
+            channels: Vec::new(),
+            start: u64::MAX,
+            end: 0,
+        };
+        writer.write_raw(MAGIC)?;
+        writer.write_record(
+            HEADER,
+            Record::default()
+                .bytes(b"ros2")
+                .bytes(concat!("rangefinder ", env!("CARGO_PKG_VERSION")).as_bytes()),
+        )?;
+        Ok(writer)
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.pos += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn write_record(&mut self, opcode: u8, record: Record) -> io::Result<()> {
+        let mut bytes = vec![opcode];
+        bytes.extend_from_slice(&(record.0.len() as u64).to_le_bytes());
+        bytes.extend(record.0);
+        self.write_raw(&bytes)
+    }
+
+    fn schema_record(id: u16, (name, definition): (&str, &str)) -> Record {
+        Record::default()
+            .u16(id)
+            .bytes(name.as_bytes())
+            .bytes(b"ros2msg")
+            .bytes(definition.as_bytes())
+    }
+
+    fn channel_record(channel: &Channel) -> Record {
+        Record::default()
+            .u16(channel.id)
+            .u16(channel.schema_id)
+            .bytes(channel.topic.as_bytes())
+            .bytes(b"cdr")
+            // no metadata
+            .u32(0)
+    }
+
+    /// Registers a topic carrying messages of type `T`, returning its channel id
+    pub fn add_channel<T: RosMessage>(&mut self, topic: &str) -> io::Result<u16> {
+        let schema_id = match self.schemas.iter().position(|(name, _)| *name == T::NAME) {
+            Some(i) => i as u16 + 1,
+            None => {
+                self.schemas.push((T::NAME, T::DEFINITION));
+                let id = self.schemas.len() as u16;
+                self.write_record(SCHEMA, Self::schema_record(id, (T::NAME, T::DEFINITION)))?;
+                id
+            }
+        };
+
+        let channel = Channel {
+            id: self.channels.len() as u16,
+            schema_id,
+            topic: topic.to_string(),
+            sequence: 0,
+            messages: 0,
+        };
+        self.write_record(CHANNEL, Self::channel_record(&channel))?;
+        self.channels.push(channel);
+        Ok(self.channels.len() as u16 - 1)
+    }
+
+    /// Writes a message, `log_time` being when it was recorded (since the UNIX epoch)
+    pub fn write<T: CdrSerialize>(&mut self, channel: u16, log_time: Duration, msg: &T) -> io::Result<()> {
+        let Some(ch) = self.channels.get_mut(channel as usize) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "unknown channel"));
+        };
+        let sequence = ch.sequence;
+        ch.sequence += 1;
+        ch.messages += 1;
+
+        let time = log_time.as_nanos() as u64;
+        self.start = self.start.min(time);
+        self.end = self.end.max(time);
+
+        self.write_record(
+            MESSAGE,
+            Record::default()
+                .u16(channel)
+                .u32(sequence)
+                .u64(time)
+                .u64(time)
+                .raw(&to_cdr(msg)),
+        )
+    }
+
+    /// Resamples a revolution and writes it as a `LaserScan` logged at its first measurement,
+    /// which needs a lidar stamping with [`SystemClock`](crate::laser::SystemClock)
+    pub fn write_revolution(
+        &mut self,
+        channel: u16,
+        revolution: &Revolution,
+        resampler: &Resampler,
+        frame_id: &str,
+    ) -> io::Result<()> {
+        let scan = LaserScan::from_revolution(revolution, resampler, frame_id);
+        self.write(channel, revolution.start_time().unwrap_or_default(), &scan)
+    }
+
+    /// Writes the summary and footer, returning the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        // CRCs are optional, zero means not computed
+        self.write_record(DATA_END, Record::default().u32(0))?;
+
+        let summary_start = self.pos;
+        let mut groups = Vec::new();
+
+        let group = self.pos;
+        for (i, schema) in self.schemas.clone().into_iter().enumerate() {
+            self.write_record(SCHEMA, Self::schema_record(i as u16 + 1, schema))?;
+        }
+        groups.push((SCHEMA, group, self.pos - group));
+
+        let group = self.pos;
+        let channels: Vec<Record> = self.channels.iter().map(Self::channel_record).collect();
+        for record in channels {
+            self.write_record(CHANNEL, record)?;
+        }
+        groups.push((CHANNEL, group, self.pos - group));
+
+        let group = self.pos;
+        let counts: BTreeMap<u16, u64> = self.channels.iter().map(|c| (c.id, c.messages)).collect();
+        let mut count_map = Record::default();
+        for (id, count) in &counts {
+            count_map = count_map.u16(*id).u64(*count);
+        }
+        let messages = counts.values().sum();
+        let (start, end) = if messages == 0 { (0, 0) } else { (self.start, self.end) };
+        self.write_record(
+            STATISTICS,
+            Record::default()
+                .u64(messages)
+                .u16(self.schemas.len() as u16)
+                .u32(self.channels.len() as u32)
+                // attachments, metadata, chunks
+                .u32(0)
+                .u32(0)
+                .u32(0)
+                .u64(start)
+                .u64(end)
+                .bytes(&count_map.0),
+        )?;
+        groups.push((STATISTICS, group, self.pos - group));
+
+        let summary_offset_start = self.pos;
+        for (opcode, start, len) in groups {
+            self.write_record(SUMMARY_OFFSET, Record::default().u8(opcode).u64(start).u64(len))?;
+        }
+
+        self.write_record(
+            FOOTER,
+            Record::default().u64(summary_start).u64(summary_offset_start).u32(0),
+        )?;
+        self.write_raw(MAGIC)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits a file into (offset, opcode, content) records, checking the magic on both ends
+    fn records(file: &[u8]) -> Vec<(usize, u8, &[u8])> {
+        assert!(file.starts_with(MAGIC) && file.ends_with(MAGIC));
+        let end = file.len() - MAGIC.len();
+        let mut records = Vec::new();
+        let mut pos = MAGIC.len();
+        while pos < end {
+            let len = u64::from_le_bytes(file[pos + 1..pos + 9].try_into().unwrap()) as usize;
+            records.push((pos, file[pos], &file[pos + 9..pos + 9 + len]));
+            pos += 9 + len;
+        }
+        assert_eq!(pos, end);
+        records
+    }
+
+    #[test]
+    fn writes_messages_and_summary() {
+        let mut writer = McapWriter::new(Vec::new()).unwrap();
+        let channel = writer.add_channel::<LaserScan>("/scan").unwrap();
+        let scan = LaserScan::default();
+        writer.write(channel, Duration::from_nanos(1_000_000_123), &scan).unwrap();
+        let file = writer.finish().unwrap();
+
+        let records = records(&file);
+        let opcodes: Vec<u8> = records.iter().map(|&(_, opcode, _)| opcode).collect();
+        assert_eq!(
+            opcodes,
+            [
+                HEADER,
+                SCHEMA,
+                CHANNEL,
+                MESSAGE,
+                DATA_END,
+                SCHEMA,
+                CHANNEL,
+                STATISTICS,
+                SUMMARY_OFFSET,
+                SUMMARY_OFFSET,
+                SUMMARY_OFFSET,
+                FOOTER
+            ]
+        );
+
+        let (_, _, header) = records[0];
+        assert_eq!(&header[..8], [4, 0, 0, 0, b'r', b'o', b's', b'2']);
+
+        // channel, sequence, log and publish time, then the CDR message
+        let (_, _, message) = records[3];
+        assert_eq!(message[..2], 0u16.to_le_bytes());
+        assert_eq!(message[2..6], 0u32.to_le_bytes());
+        assert_eq!(message[6..14], 1_000_000_123u64.to_le_bytes());
+        assert_eq!(message[14..22], 1_000_000_123u64.to_le_bytes());
+        assert_eq!(message[22..], to_cdr(&scan));
+
+        // one message, on channel 0
+        let (_, _, statistics) = records[7];
+        assert_eq!(statistics[..8], 1u64.to_le_bytes());
+        assert_eq!(statistics[statistics.len() - 10..], [0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+
+        let (_, _, footer) = records[11];
+        let summary_start = u64::from_le_bytes(footer[..8].try_into().unwrap()) as usize;
+        let summary_offset_start = u64::from_le_bytes(footer[8..16].try_into().unwrap()) as usize;
+        assert_eq!(summary_start, records[5].0);
+        assert_eq!(summary_offset_start, records[8].0);
+    }
+}
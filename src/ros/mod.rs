@@ -0,0 +1,13 @@
+//! ROS 2 message types and their CDR encoding, usable without a ROS installation
+//!
+//! Serialized messages can be published through any DDS or zenoh transport, or recorded.
+
+pub mod cdr;
+#[cfg(feature = "foxglove")]
+pub mod foxglove;
+pub mod mcap;
+pub mod msg;
+
+pub use cdr::{to_cdr, CdrSerialize, CdrWriter};
+pub use mcap::McapWriter;
+pub use msg::RosMessage;
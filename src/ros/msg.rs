@@ -0,0 +1,284 @@
+use crate::laser::Revolution;
+use crate::ros::cdr::{CdrSerialize, CdrWriter};
+use crate::scan;
+use crate::scan::{Mounting, Resampler};
+use std::time::Duration;
+
+/// A message with a ROS 2 type name and `.msg` definition, as stored in recordings
+pub trait RosMessage {
+    /// e.g. `sensor_msgs/msg/LaserScan`
+    const NAME: &'static str;
+    /// the `.msg` text followed by the definitions of all nested types
+    const DEFINITION: &'static str;
+}
+
+/// Separates nested type definitions in a `ros2msg` schema
+macro_rules! dependency {
+    ($name:literal) => {
+        concat!(
+            "================================================================================\n",
+            "MSG: ",
+            $name,
+            "\n"
+        )
+    };
+}
+
+/// Definitions of `std_msgs/Header` and its fields
+macro_rules! header_definition {
+    () => {
+        concat!(
+            dependency!("std_msgs/Header"),
+            "builtin_interfaces/Time stamp\n",
+            "string frame_id\n",
+            dependency!("builtin_interfaces/Time"),
+            "int32 sec\n",
+            "uint32 nanosec\n",
+        )
+    };
+}
+
+/// `builtin_interfaces/Time`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Time {
+    pub sec: i32,
+    pub nanosec: u32,
+}
+
+impl From<Duration> for Time {
+    fn from(d: Duration) -> Self {
+        Time {
+            sec: d.as_secs() as i32,
+            nanosec: d.subsec_nanos(),
+        }
+    }
+}
+
+impl CdrSerialize for Time {
+    fn serialize(&self, w: &mut CdrWriter) {
+        w.write_i32(self.sec);
+        w.write_u32(self.nanosec);
+    }
+}
+
+/// `std_msgs/Header`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Header {
+    pub stamp: Time,
+    pub frame_id: String,
+}
+
+impl Header {
+    pub fn new(stamp: Duration, frame_id: &str) -> Header {
+        Header {
+            stamp: stamp.into(),
+            frame_id: frame_id.to_string(),
+        }
+    }
+}
+
+impl CdrSerialize for Header {
+    fn serialize(&self, w: &mut CdrWriter) {
+        self.stamp.serialize(w);
+        w.write_string(&self.frame_id);
+    }
+}
+
+/// `sensor_msgs/LaserScan`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LaserScan {
+    pub header: Header,
+    pub angle_min: f32,
+    pub angle_max: f32,
+    pub angle_increment: f32,
+    pub time_increment: f32,
+    pub scan_time: f32,
+    pub range_min: f32,
+    pub range_max: f32,
+    pub ranges: Vec<f32>,
+    pub intensities: Vec<f32>,
+}
+
+impl LaserScan {
+    /// Wraps a resampled scan, stamped with the measurement of its `angle_min` beam
+    pub fn new(scan: &scan::LaserScan, frame_id: &str) -> LaserScan {
+        LaserScan {
+            header: Header::new(scan.stamp, frame_id),
+            angle_min: scan.angle_min,
+            angle_max: scan.angle_max,
+            angle_increment: scan.angle_increment,
+            time_increment: scan.time_increment,
+            scan_time: scan.scan_time,
+            range_min: scan.range_min,
+            range_max: scan.range_max,
+            ranges: scan.ranges.clone(),
+            intensities: scan.intensities.clone(),
+        }
+    }
+
+    /// Resamples a revolution onto the grid of `resampler`
+    pub fn from_revolution(revolution: &Revolution, resampler: &Resampler, frame_id: &str) -> LaserScan {
+        LaserScan::new(&resampler.resample(revolution), frame_id)
+    }
+}
+
+impl RosMessage for LaserScan {
+    const NAME: &'static str = "sensor_msgs/msg/LaserScan";
+    const DEFINITION: &'static str = concat!(
+        "std_msgs/Header header\n",
+        "float32 angle_min\n",
+        "float32 angle_max\n",
+        "float32 angle_increment\n",
+        "float32 time_increment\n",
+        "float32 scan_time\n",
+        "float32 range_min\n",
+        "float32 range_max\n",
+        "float32[] ranges\n",
+        "float32[] intensities\n",
+        header_definition!(),
+    );
+}
+
+impl CdrSerialize for LaserScan {
+    fn serialize(&self, w: &mut CdrWriter) {
+        self.header.serialize(w);
+        w.write_f32(self.angle_min);
+        w.write_f32(self.angle_max);
+        w.write_f32(self.angle_increment);
+        w.write_f32(self.time_increment);
+        w.write_f32(self.scan_time);
+        w.write_f32(self.range_min);
+        w.write_f32(self.range_max);
+        w.write_f32_seq(&self.ranges);
+        w.write_f32_seq(&self.intensities);
+    }
+}
+
+/// `sensor_msgs/PointField`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PointField {
+    pub name: String,
+    pub offset: u32,
+    pub datatype: u8,
+    pub count: u32,
+}
+
+impl PointField {
+    pub const INT8: u8 = 1;
+    pub const UINT8: u8 = 2;
+    pub const INT16: u8 = 3;
+    pub const UINT16: u8 = 4;
+    pub const INT32: u8 = 5;
+    pub const UINT32: u8 = 6;
+    pub const FLOAT32: u8 = 7;
+    pub const FLOAT64: u8 = 8;
+
+    fn float32(name: &str, offset: u32) -> PointField {
+        PointField {
+            name: name.to_string(),
+            offset,
+            datatype: PointField::FLOAT32,
+            count: 1,
+        }
+    }
+}
+
+impl CdrSerialize for PointField {
+    fn serialize(&self, w: &mut CdrWriter) {
+        w.write_string(&self.name);
+        w.write_u32(self.offset);
+        w.write_u8(self.datatype);
+        w.write_u32(self.count);
+    }
+}
+
+/// `sensor_msgs/PointCloud2`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PointCloud2 {
+    pub header: Header,
+    pub height: u32,
+    pub width: u32,
+    pub fields: Vec<PointField>,
+    pub is_bigendian: bool,
+    pub point_step: u32,
+    pub row_step: u32,
+    pub data: Vec<u8>,
+    pub is_dense: bool,
+}
+
+impl PointCloud2 {
+    /// Unorganized cloud of a revolution's valid samples with float32 `x`, `y`, `z` and
+    /// `intensity` fields, placed by `mounting` (use the default for the lidar's own frame)
+    pub fn from_revolution(revolution: &Revolution, mounting: &Mounting, frame_id: &str) -> PointCloud2 {
+        const POINT_STEP: u32 = 16;
+
+        let mut data = Vec::with_capacity(revolution.len() * POINT_STEP as usize);
+        for sample in revolution.samples.iter().filter(|s| s.is_valid()) {
+            let p = mounting.to_base(sample);
+            for v in [p.x as f32, p.y as f32, 0.0, sample.intensity.unwrap_or(0) as f32] {
+                data.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+
+        let width = data.len() as u32 / POINT_STEP;
+        PointCloud2 {
+            header: Header::new(revolution.start_time().unwrap_or_default(), frame_id),
+            height: 1,
+            width,
+            fields: vec![
+                PointField::float32("x", 0),
+                PointField::float32("y", 4),
+                PointField::float32("z", 8),
+                PointField::float32("intensity", 12),
+            ],
+            is_bigendian: false,
+            point_step: POINT_STEP,
+            row_step: width * POINT_STEP,
+            data,
+            is_dense: true,
+        }
+    }
+}
+
+impl RosMessage for PointCloud2 {
+    const NAME: &'static str = "sensor_msgs/msg/PointCloud2";
+    const DEFINITION: &'static str = concat!(
+        "std_msgs/Header header\n",
+        "uint32 height\n",
+        "uint32 width\n",
+        "PointField[] fields\n",
+        "bool is_bigendian\n",
+        "uint32 point_step\n",
+        "uint32 row_step\n",
+        "uint8[] data\n",
+        "bool is_dense\n",
+        header_definition!(),
+        dependency!("sensor_msgs/PointField"),
+        "uint8 INT8=1\n",
+        "uint8 UINT8=2\n",
+        "uint8 INT16=3\n",
+        "uint8 UINT16=4\n",
+        "uint8 INT32=5\n",
+        "uint8 UINT32=6\n",
+        "uint8 FLOAT32=7\n",
+        "uint8 FLOAT64=8\n",
+        "string name\n",
+        "uint32 offset\n",
+        "uint8 datatype\n",
+        "uint32 count\n",
+    );
+}
+
+impl CdrSerialize for PointCloud2 {
+    fn serialize(&self, w: &mut CdrWriter) {
+        self.header.serialize(w);
+        w.write_u32(self.height);
+        w.write_u32(self.width);
+        w.write_seq(&self.fields);
+        w.write_bool(self.is_bigendian);
+        w.write_u32(self.point_step);
+        w.write_u32(self.row_step);
+        w.write_bytes(&self.data);
+        w.write_bool(self.is_dense);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::laser::Revolution;
+use crate::scan::geometry::{Point2, Pose2};
+use std::time::Duration;
+
+/// How the lidar moves over time
+pub trait Motion {
+    /// Pose of the lidar at time `from` relative to its pose at time `to`, `None` if unknown
+    fn relative(&self, from: Duration, to: Duration) -> Option<Pose2>;
+}
+
+/// Constant velocity of the lidar, in its own frame
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Twist {
+    /// forward speed (m/s)
+    pub vx: f64,
+    /// leftward speed (m/s)
+    pub vy: f64,
+    /// counter-clockwise turn rate (rad/s)
+    pub wz: f64,
+}
+
+impl Motion for Twist {
+    fn relative(&self, from: Duration, to: Duration) -> Option<Pose2> {
+        let dt = from.as_secs_f64() - to.as_secs_f64();
+        let yaw = self.wz * dt;
+
+        // moving along an arc rather than first turning and then translating
+        if yaw.abs() < 1e-9 {
+            return Some(Pose2::new(self.vx * dt, self.vy * dt, yaw));
+        }
+        let (sin, cos) = yaw.sin_cos();
+        Some(Pose2::new(
+            (self.vx * sin - self.vy * (1.0 - cos)) / self.wz,
+            (self.vx * (1.0 - cos) + self.vy * sin) / self.wz,
+            yaw,
+        ))
+    }
+}
+
+/// Poses of the lidar in a fixed frame, looked up by sample timestamp (e.g. from odometry)
+pub struct Odometry<F>(pub F);
+
+impl<F: Fn(Duration) -> Option<Pose2>> Motion for Odometry<F> {
+    fn relative(&self, from: Duration, to: Duration) -> Option<Pose2> {
+        Some((self.0)(to)?.inverse().compose(&(self.0)(from)?))
+    }
+}
+
+/// Removes the smear caused by the lidar moving during a revolution.
+///
+/// Every sample is moved into the lidar's frame at the time of the revolution's last sample.
+/// Needs the per-sample timestamps of the scan.
+pub struct Deskew<M> {
+    motion: M,
+}
+
+impl<M: Motion> Deskew<M> {
+    pub fn new(motion: M) -> Deskew<M> {
+        Deskew { motion }
+    }
+
+    pub fn motion(&self) -> &M {
+        &self.motion
+    }
+
+    /// Replaces the motion, e.g. with an updated velocity estimate
+    pub fn set_motion(&mut self, motion: M) {
+        self.motion = motion;
+    }
+
+    /// De-skews a revolution in place, returning how many samples had a known pose
+    pub fn apply(&self, revolution: &mut Revolution) -> usize {
+        let Some(end) = revolution.end_time() else {
+            return 0;
+        };
+
+        let mut corrected = 0;
+        for sample in &mut revolution.samples {
+            let Some(pose) = self.motion.relative(sample.timestamp, end) else {
+                continue;
+            };
+
+            if sample.is_valid() {
+                let (angle, distance) = pose.transform(Point2::from_sample(sample)).to_polar();
+                sample.set_angle(angle);
+                sample.set_distance(distance);
+            } else {
+                // nothing was hit, only the direction changes
+                sample.set_angle(sample.angle() - pose.yaw.to_degrees() as f32);
+            }
+            corrected += 1;
+        }
+
+        corrected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::laser::Sample;
+    use std::f64::consts::FRAC_PI_2;
+
+    /// Samples at the given angle (degrees), distance (mm) and time (ms)
+    fn revolution(samples: &[(f32, f32, u64)]) -> Revolution {
+        let samples = samples
+            .iter()
+            .map(|&(angle, distance, ms)| Sample {
+                timestamp: Duration::from_millis(ms),
+                ..Sample::at(angle, distance)
+            })
+            .collect();
+        Revolution { samples }
+    }
+
+    #[test]
+    fn moves_samples_into_the_final_frame_when_driving() {
+        let mut revolution = revolution(&[(0.0, 2000.0, 0), (90.0, 500.0, 50), (180.0, 1000.0, 100)]);
+        let deskew = Deskew::new(Twist { vx: 1.0, vy: 0.0, wz: 0.0 });
+        assert_eq!(deskew.apply(&mut revolution), 3);
+
+        // the lidar drove 10 cm towards the wall ahead since it was measured
+        assert_eq!(revolution.samples[0].angle(), 0.0);
+        assert_eq!(revolution.samples[0].distance(), 1900.0);
+        // 5 cm past the one on the right
+        let (angle, distance) = (revolution.samples[1].angle(), revolution.samples[1].distance());
+        assert!((angle - 95.71).abs() < 0.02, "{angle}");
+        assert!((distance - 502.5).abs() < 0.5, "{distance}");
+        // the last sample is the reference
+        assert_eq!(revolution.samples[2].angle(), 180.0);
+        assert_eq!(revolution.samples[2].distance(), 1000.0);
+    }
+
+    #[test]
+    fn turns_samples_with_the_lidar() {
+        let mut revolution = revolution(&[(0.0, 1000.0, 0), (0.0, 0.0, 0), (350.0, 1000.0, 1000)]);
+        // a quarter turn counter-clockwise per second
+        Deskew::new(Twist { vx: 0.0, vy: 0.0, wz: FRAC_PI_2 }).apply(&mut revolution);
+
+        // what was ahead a second ago is now on the right, clockwise from the front
+        assert_eq!(revolution.samples[0].angle(), 90.0);
+        assert!((revolution.samples[0].distance() - 1000.0).abs() <= 0.25);
+        // an invalid sample only changes direction
+        assert_eq!(revolution.samples[1].angle(), 90.0);
+        assert!(!revolution.samples[1].is_valid());
+        assert_eq!(revolution.samples[2].angle(), 350.0);
+    }
+
+    #[test]
+    fn follows_odometry_and_skips_unknown_poses() {
+        let driving = Twist { vx: 1.0, vy: 0.0, wz: 0.0 };
+        // odometry of the same motion, only known from 20 ms on
+        let odometry = Odometry(|t: Duration| {
+            (t >= Duration::from_millis(20)).then(|| Pose2::new(t.as_secs_f64(), 0.0, 0.0))
+        });
+
+        let samples = [(0.0, 2000.0, 0), (0.0, 2000.0, 50), (180.0, 1000.0, 100)];
+        let mut by_twist = revolution(&samples);
+        let mut by_odometry = revolution(&samples);
+        Deskew::new(driving).apply(&mut by_twist);
+        assert_eq!(Deskew::new(odometry).apply(&mut by_odometry), 2);
+
+        assert_eq!(by_odometry.samples[0].distance(), 2000.0);
+        assert_eq!(by_odometry.samples[1].distance(), by_twist.samples[1].distance());
+        assert_eq!(by_odometry.samples[1].distance(), 1950.0);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::laser::{Revolution, Sample, ScanSession};
+use crate::scan::mask::MaskFilter;
+
+/// A stage of a [`FilterChain`]
+pub trait SampleFilter: Send {
+    /// Name of the stage in [`StageStats`]
+    fn name(&self) -> &str;
+
+    /// Passes a sample on, possibly modified, or drops it by returning `None`
+    fn apply(&mut self, sample: Sample) -> Option<Sample>;
+}
+
+/// Drops samples without a valid measurement (zero or non-finite distance)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InvalidFilter;
+
+impl SampleFilter for InvalidFilter {
+    fn name(&self) -> &str {
+        "invalid"
+    }
+
+    fn apply(&mut self, sample: Sample) -> Option<Sample> {
+        sample.is_valid().then_some(sample)
+    }
+}
+
+/// What a [`RangeFilter`] does with samples outside its window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangePolicy {
+    /// Remove them from the stream
+    Drop,
+    /// Move them onto the nearest edge of the window; samples without a return are left as they
+    /// are
+    Clamp,
+}
+
+/// Limits samples to a range window (mm), dropping or clamping those outside it.
+///
+/// Dropping suits range limits of the sensor, clamping suits consumers that need a bounded
+/// range but should still see that something was there, e.g. an occupancy grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeFilter {
+    pub min: f32,
+    pub max: f32,
+    pub policy: RangePolicy,
+}
+
+impl RangeFilter {
+    /// Drops ranges past the maximum the lidar reported for the session's scan mode, keeping
+    /// every range if it reported none
+    pub fn for_session(session: &ScanSession) -> RangeFilter {
+        RangeFilter {
+            min: 0.0,
+            max: session.max_distance().map_or(f32::INFINITY, |max| max * 1000.0),
+            policy: RangePolicy::Drop,
+        }
+    }
+}
+
+impl SampleFilter for RangeFilter {
+    fn name(&self) -> &str {
+        "range"
+    }
+
+    fn apply(&mut self, mut sample: Sample) -> Option<Sample> {
+        let distance = sample.distance();
+        if self.min <= distance && distance <= self.max {
+            return Some(sample);
+        }
+        match self.policy {
+            RangePolicy::Drop => None,
+            RangePolicy::Clamp if !sample.is_valid() => Some(sample),
+            RangePolicy::Clamp => {
+                sample.set_distance(distance.clamp(self.min, self.max));
+                Some(sample)
+            }
+        }
+    }
+}
+
+/// Drops samples whose quality (intensity) is below a threshold; dense scan samples, which carry
+/// no intensity, pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QualityFilter {
+    pub min_intensity: u8,
+}
+
+impl SampleFilter for QualityFilter {
+    fn name(&self) -> &str {
+        "quality"
+    }
+
+    fn apply(&mut self, sample: Sample) -> Option<Sample> {
+        sample.intensity.is_none_or(|intensity| intensity >= self.min_intensity).then_some(sample)
+    }
+}
+
+impl SampleFilter for MaskFilter {
+    fn name(&self) -> &str {
+        "mask"
+    }
+
+    fn apply(&mut self, sample: Sample) -> Option<Sample> {
+        MaskFilter::apply(self, sample)
+    }
+}
+
+/// How many samples a stage of a [`FilterChain`] removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageStats {
+    pub name: String,
+    /// samples that reached the stage
+    pub seen: u64,
+    /// samples the stage dropped
+    pub removed: u64,
+}
+
+struct Stage {
+    filter: Box<dyn SampleFilter>,
+    seen: u64,
+    removed: u64,
+}
+
+/// Filters applied one after another, counting what each removes
+#[derive(Default)]
+pub struct FilterChain {
+    stages: Vec<Stage>,
+}
+
+impl FilterChain {
+    pub fn new() -> FilterChain {
+        FilterChain::default()
+    }
+
+    /// The usual chain for a session's samples: invalid ranges, its mode's range limit, then a
+    /// quality threshold
+    pub fn standard(session: &ScanSession, min_intensity: u8) -> FilterChain {
+        FilterChain::new()
+            .with(InvalidFilter)
+            .with(RangeFilter::for_session(session))
+            .with(QualityFilter { min_intensity })
+    }
+
+    /// Appends a stage
+    pub fn with(mut self, filter: impl SampleFilter + 'static) -> FilterChain {
+        self.push(filter);
+        self
+    }
+
+    /// Appends a stage
+    pub fn push(&mut self, filter: impl SampleFilter + 'static) {
+        self.stages.push(Stage {
+            filter: Box::new(filter),
+            seen: 0,
+            removed: 0,
+        });
+    }
+
+    /// Runs a sample through all stages, `None` if one of them dropped it
+    pub fn apply(&mut self, mut sample: Sample) -> Option<Sample> {
+        for stage in &mut self.stages {
+            stage.seen += 1;
+            match stage.filter.apply(sample) {
+                Some(s) => sample = s,
+                None => {
+                    stage.removed += 1;
+                    return None;
+                }
+            }
+        }
+        Some(sample)
+    }
+
+    /// Filters a whole revolution in place
+    pub fn apply_revolution(&mut self, revolution: &mut Revolution) {
+        let samples = std::mem::take(&mut revolution.samples);
+        revolution.samples = samples.into_iter().filter_map(|s| self.apply(s)).collect();
+    }
+
+    /// Filters a sample stream
+    pub fn filter<'a, I>(&'a mut self, samples: I) -> impl Iterator<Item = Sample> + 'a
+    where
+        I: IntoIterator<Item = Sample>,
+        I::IntoIter: 'a,
+    {
+        samples.into_iter().filter_map(move |s| self.apply(s))
+    }
+
+    /// Counters of every stage, in order
+    pub fn stats(&self) -> Vec<StageStats> {
+        self.stages
+            .iter()
+            .map(|stage| StageStats {
+                name: stage.filter.name().to_string(),
+                seen: stage.seen,
+                removed: stage.removed,
+            })
+            .collect()
+    }
+
+    pub fn reset_stats(&mut self) {
+        for stage in &mut self.stages {
+            stage.seen = 0;
+            stage.removed = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distances(chain: &mut FilterChain, distances: &[f32]) -> Vec<f32> {
+        let samples = distances.iter().map(|&d| Sample::at(0.0, d));
+        chain.filter(samples).map(|s| s.distance()).collect()
+    }
+
+    #[test]
+    fn drops_ranges_outside_the_window() {
+        let range = RangeFilter { min: 100.0, max: 2000.0, policy: RangePolicy::Drop };
+        let mut chain = FilterChain::new().with(range);
+
+        assert_eq!(distances(&mut chain, &[0.0, 50.0, 100.0, 2000.0, 2000.25]), vec![100.0, 2000.0]);
+        assert_eq!(chain.stats()[0].removed, 3);
+    }
+
+    #[test]
+    fn clamps_ranges_onto_the_window() {
+        let range = RangeFilter { min: 100.0, max: 2000.0, policy: RangePolicy::Clamp };
+        let mut chain = FilterChain::new().with(range);
+
+        assert_eq!(
+            distances(&mut chain, &[0.0, 50.0, 1000.0, 2500.0]),
+            vec![0.0, 100.0, 1000.0, 2000.0]
+        );
+        assert_eq!(chain.stats()[0].removed, 0);
+    }
+
+    #[test]
+    fn drops_samples_below_the_quality_threshold() {
+        let mut quality = QualityFilter { min_intensity: 10 };
+        let sample = |intensity| Sample { intensity, ..Sample::at(0.0, 1000.0) };
+
+        assert!(quality.apply(sample(Some(9))).is_none());
+        assert!(quality.apply(sample(Some(10))).is_some());
+        // dense samples have nothing to judge by
+        assert!(quality.apply(sample(None)).is_some());
+    }
+
+    #[test]
+    fn counts_what_each_stage_removes() {
+        let range = RangeFilter { min: 0.0, max: 2000.0, policy: RangePolicy::Drop };
+        let mut chain = FilterChain::new().with(InvalidFilter).with(range);
+
+        assert_eq!(distances(&mut chain, &[0.0, 1000.0, 3000.0]), vec![1000.0]);
+        let removed: Vec<_> = chain.stats().iter().map(|s| (s.seen, s.removed)).collect();
+        assert_eq!(removed, vec![(3, 1), (2, 1)]);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::laser::Sample;
+use std::f64::consts::PI;
+
+/// Direction in which an angle grows, as seen from above
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AngleDirection {
+    /// The lidar's own convention: degrees in `[0, 360)`
+    Clockwise,
+    /// The ROS (REP 103) convention: radians in `[-π, π)`
+    CounterClockwise,
+}
+
+impl AngleDirection {
+    /// Converts a sample's angle (clockwise degrees) into this convention
+    pub fn from_device(self, angle: f32) -> f64 {
+        match self {
+            AngleDirection::Clockwise => (angle as f64).rem_euclid(360.0),
+            AngleDirection::CounterClockwise => wrap_pi(-(angle as f64).to_radians()),
+        }
+    }
+
+    /// Converts an angle in this convention back into clockwise device degrees
+    pub fn to_device(self, angle: f64) -> f32 {
+        match self {
+            AngleDirection::Clockwise => angle.rem_euclid(360.0) as f32,
+            AngleDirection::CounterClockwise => (-angle.to_degrees()).rem_euclid(360.0) as f32,
+        }
+    }
+}
+
+/// Wraps an angle (radians) into `[-π, π)`
+pub fn wrap_pi(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+/// A point in a right-handed, counter-clockwise plane (metres)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Point2 {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point2 {
+    pub fn new(x: f64, y: f64) -> Point2 {
+        Point2 { x, y }
+    }
+
+    /// Point measured by a sample, in the lidar's own frame (x forward, y left)
+    pub fn from_sample(sample: &Sample) -> Point2 {
+        let angle = AngleDirection::CounterClockwise.from_device(sample.angle());
+        let range = sample.distance() as f64 / 1000.0;
+        Point2::new(range * angle.cos(), range * angle.sin())
+    }
+
+    /// Clockwise angle (degrees, in `[0, 360)`) and distance (mm) of this point, as in a sample
+    pub fn to_polar(self) -> (f32, f32) {
+        let angle = AngleDirection::CounterClockwise.to_device(self.y.atan2(self.x));
+        let distance = self.x.hypot(self.y) * 1000.0;
+        (angle, distance as f32)
+    }
+}
+
+/// Position and heading of a frame within another (metres, radians counter-clockwise)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pose2 {
+    pub x: f64,
+    pub y: f64,
+    pub yaw: f64,
+}
+
+impl Pose2 {
+    pub fn new(x: f64, y: f64, yaw: f64) -> Pose2 {
+        Pose2 { x, y, yaw }
+    }
+
+    /// Maps a point from this frame into the parent frame
+    pub fn transform(&self, p: Point2) -> Point2 {
+        let (sin, cos) = self.yaw.sin_cos();
+        Point2::new(
+            self.x + cos * p.x - sin * p.y,
+            self.y + sin * p.x + cos * p.y,
+        )
+    }
+
+    /// The pose of `other` (given relative to this frame) in the parent frame
+    pub fn compose(&self, other: &Pose2) -> Pose2 {
+        let origin = self.transform(Point2::new(other.x, other.y));
+        Pose2::new(origin.x, origin.y, self.yaw + other.yaw)
+    }
+
+    /// The pose of the parent frame relative to this one
+    pub fn inverse(&self) -> Pose2 {
+        let (sin, cos) = self.yaw.sin_cos();
+        Pose2::new(
+            -cos * self.x - sin * self.y,
+            sin * self.x - cos * self.y,
+            -self.yaw,
+        )
+    }
+}
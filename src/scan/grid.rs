@@ -0,0 +1,229 @@
+use crate::laser::Revolution;
+use crate::scan::geometry::AngleDirection;
+use std::f64::consts::{PI, TAU};
+use std::time::Duration;
+
+/// A revolution resampled onto evenly spaced angles, laid out like ROS `sensor_msgs/LaserScan`
+///
+/// Angles are counter-clockwise radians (REP 103), ranges metres.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LaserScan {
+    /// measurement time of the `angle_min` beam, interpolated from the revolution's first sample
+    pub stamp: Duration,
+    pub angle_min: f32,
+    pub angle_max: f32,
+    pub angle_increment: f32,
+    /// time between the measurements of consecutive beams (seconds)
+    ///
+    /// Negative, as the lidar sweeps clockwise against the growing angles. Beam `i` was measured
+    /// at `stamp + i * time_increment`, or a `scan_time` later for beams that puts before the
+    /// revolution's first sample.
+    pub time_increment: f32,
+    /// time the lidar took to sweep the revolution, from its first to its last measurement
+    /// (seconds)
+    pub scan_time: f32,
+    pub range_min: f32,
+    pub range_max: f32,
+    pub ranges: Vec<f32>,
+    pub intensities: Vec<f32>,
+}
+
+impl LaserScan {
+    /// Angle of the `i`th range
+    pub fn angle(&self, i: usize) -> f32 {
+        self.angle_min + i as f32 * self.angle_increment
+    }
+}
+
+/// How samples falling into the same bin are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinPolicy {
+    /// The sample closest to the bin's angle
+    Nearest,
+    /// The shortest range, the conservative choice for obstacle avoidance
+    Min,
+    /// The mean of all samples
+    Average,
+}
+
+/// What empty bins hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyBin {
+    /// NaN: no valid measurement
+    Nan,
+    /// +inf: nothing within range (REP 117)
+    Infinity,
+}
+
+impl EmptyBin {
+    fn value(self) -> f32 {
+        match self {
+            EmptyBin::Nan => f32::NAN,
+            EmptyBin::Infinity => f32::INFINITY,
+        }
+    }
+}
+
+/// Bins revolutions into a fixed grid of `bins` ranges from `angle_min` to `angle_max`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Resampler {
+    pub bins: usize,
+    /// counter-clockwise radians
+    pub angle_min: f32,
+    /// counter-clockwise radians, the angle of the last bin
+    pub angle_max: f32,
+    /// metres
+    pub range_min: f32,
+    /// metres
+    pub range_max: f32,
+    pub policy: BinPolicy,
+    pub empty: EmptyBin,
+}
+
+impl Resampler {
+    /// A full circle of `bins` ranges starting behind the lidar
+    pub fn full_circle(bins: usize, range_max: f32) -> Resampler {
+        let bins = bins.max(1);
+        let increment = 2.0 * PI / bins as f64;
+        Resampler {
+            bins,
+            angle_min: -PI as f32,
+            angle_max: (PI - increment) as f32,
+            range_min: 0.0,
+            range_max,
+            policy: BinPolicy::Nearest,
+            empty: EmptyBin::Nan,
+        }
+    }
+
+    fn increment(&self) -> f64 {
+        if self.bins < 2 {
+            return 0.0;
+        }
+        (self.angle_max - self.angle_min) as f64 / (self.bins - 1) as f64
+    }
+
+    /// Resamples a revolution
+    pub fn resample(&self, revolution: &Revolution) -> LaserScan {
+        let increment = self.increment();
+        // (range sum or best range, intensity sum or best intensity, count, offset from bin centre)
+        let mut cells = vec![(0.0f32, 0.0f32, 0usize, f64::INFINITY); self.bins];
+
+        for sample in &revolution.samples {
+            let range = sample.distance() / 1000.0;
+            if !sample.is_valid() || range < self.range_min || range > self.range_max {
+                continue;
+            }
+
+            let angle = AngleDirection::CounterClockwise.from_device(sample.angle());
+            let offset = (angle - self.angle_min as f64).rem_euclid(2.0 * PI);
+            let (i, off) = if increment > 0.0 {
+                let i = (offset / increment).round();
+                (i as usize, (offset - i * increment).abs())
+            } else {
+                (0, offset.min(2.0 * PI - offset))
+            };
+            // past angle_max, unless it wraps around to the first bin
+            let i = if i < self.bins {
+                i
+            } else if (2.0 * PI - offset) <= increment / 2.0 {
+                0
+            } else {
+                continue;
+            };
+
+            let cell = &mut cells[i];
+            let intensity = sample.intensity.unwrap_or(0) as f32;
+            match self.policy {
+                BinPolicy::Nearest if off < cell.3 => *cell = (range, intensity, 1, off),
+                BinPolicy::Min if cell.2 == 0 || range < cell.0 => *cell = (range, intensity, 1, off),
+                BinPolicy::Average => {
+                    cell.0 += range;
+                    cell.1 += intensity;
+                    cell.2 += 1;
+                }
+                _ => {}
+            }
+        }
+
+        let (ranges, intensities) = cells
+            .iter()
+            .map(|&(range, intensity, count, _)| match count {
+                0 => (self.empty.value(), 0.0),
+                n => (range / n as f32, intensity / n as f32),
+            })
+            .unzip();
+
+        let scan_time = revolution.duration().as_secs_f64();
+        // how far the sweep had turned from the first sample when it passed angle_min
+        let stamp = revolution.samples.first().map_or(Duration::ZERO, |first| {
+            let angle_min = AngleDirection::CounterClockwise.to_device(self.angle_min as f64);
+            let swept = (angle_min - first.angle()).rem_euclid(360.0) as f64;
+            first.timestamp + Duration::from_secs_f64(scan_time * swept / 360.0)
+        });
+
+        LaserScan {
+            stamp,
+            angle_min: self.angle_min,
+            angle_max: self.angle_max,
+            angle_increment: increment as f32,
+            time_increment: (-scan_time * increment / TAU) as f32,
+            scan_time: scan_time as f32,
+            range_min: self.range_min,
+            range_max: self.range_max,
+            ranges,
+            intensities,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::laser::Sample;
+    use std::time::Duration;
+
+    /// One sample per degree, measured a millisecond apart from 1 s on
+    fn revolution(count: u16) -> Revolution {
+        let samples = (0..count)
+            .map(|i| Sample {
+                start: i == 0,
+                intensity: Some(10),
+                angle_q6: i * 64,
+                distance_q2: 4000,
+                received: Duration::ZERO,
+                timestamp: Duration::from_millis(1000 + i as u64),
+            })
+            .collect();
+        Revolution { samples }
+    }
+
+    #[test]
+    fn times_each_beam() {
+        let scan = Resampler::full_circle(4, 12.0).resample(&revolution(360));
+        assert_eq!(scan.scan_time, 0.359);
+        // a quarter of the sweep, backwards
+        assert!((scan.time_increment + 0.359 / 4.0).abs() < 1e-6);
+
+        // behind, right, ahead and left were measured half way, a quarter way, at the start and
+        // three quarters way through the revolution
+        let expected = [1.180, 1.090, 1.000, 1.270];
+        for (i, expected) in expected.into_iter().enumerate() {
+            let mut time = scan.stamp.as_secs_f64() + i as f64 * scan.time_increment as f64;
+            if time < 1.0 {
+                time += scan.scan_time as f64;
+            }
+            assert!((time - expected).abs() < 1e-3, "beam {i} at {time}");
+        }
+    }
+
+    #[test]
+    fn stamps_short_revolutions_at_their_first_sample() {
+        let resampler = Resampler::full_circle(360, 12.0);
+        for count in [0, 1] {
+            let scan = resampler.resample(&revolution(count));
+            assert_eq!((scan.scan_time, scan.time_increment), (0.0, 0.0));
+        }
+        assert_eq!(resampler.resample(&revolution(1)).stamp, Duration::from_secs(1));
+    }
+}
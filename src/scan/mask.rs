@@ -0,0 +1,239 @@
+use crate::laser::{Revolution, Sample};
+use crate::scan::geometry::Point2;
+use crate::scan::mounting::Mounting;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+/// A range of device angles (clockwise degrees), wrapping through 0 if `start > end`
+///
+/// Spans of 360° or more cover the full circle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sector {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Sector {
+    pub fn new(start: f32, end: f32) -> Sector {
+        if end - start >= 360.0 {
+            return Sector { start: 0.0, end: 360.0 };
+        }
+        Sector {
+            start: start.rem_euclid(360.0),
+            end: end.rem_euclid(360.0),
+        }
+    }
+
+    pub fn contains(&self, angle: f32) -> bool {
+        let angle = angle.rem_euclid(360.0);
+        if self.start <= self.end {
+            self.start <= angle && angle <= self.end
+        } else {
+            angle >= self.start || angle <= self.end
+        }
+    }
+}
+
+/// What happens to masked samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaskAction {
+    /// Remove them from the stream
+    Drop,
+    /// Keep them as invalid measurements (distance 0), preserving angular spacing
+    ///
+    /// These look like samples without a return; [`Mask::apply_revolution`] reports which ones
+    /// were masked.
+    Invalidate,
+}
+
+/// Parts of the field of view blocked by the robot itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mask {
+    /// blocked angular sectors
+    pub sectors: Vec<Sector>,
+    /// outline of the robot in the base frame; hits inside it are masked
+    pub footprint: Vec<Point2>,
+    /// places samples in the base frame for the footprint test
+    pub mounting: Mounting,
+    pub action: MaskAction,
+}
+
+impl Default for Mask {
+    /// Masks nothing
+    fn default() -> Self {
+        Mask {
+            sectors: Vec::new(),
+            footprint: Vec::new(),
+            mounting: Mounting::default(),
+            action: MaskAction::Drop,
+        }
+    }
+}
+
+impl Mask {
+    /// Whether a sample falls into a blocked sector or hit the robot's body
+    pub fn is_masked(&self, sample: &Sample) -> bool {
+        if self.sectors.iter().any(|s| s.contains(sample.angle())) {
+            return true;
+        }
+        sample.is_valid()
+            && self.footprint.len() >= 3
+            && contains(&self.footprint, self.mounting.to_base(sample))
+    }
+
+    /// Passes a sample through the mask, `None` if it was dropped
+    pub fn apply(&self, mut sample: Sample) -> Option<Sample> {
+        if !self.is_masked(&sample) {
+            return Some(sample);
+        }
+        match self.action {
+            MaskAction::Drop => None,
+            MaskAction::Invalidate => {
+                sample.distance_q2 = 0;
+                sample.intensity = sample.intensity.map(|_| 0);
+                Some(sample)
+            }
+        }
+    }
+
+    /// Masks a whole revolution in place, returning the indices of the masked samples
+    ///
+    /// Indices refer to the revolution as passed in, which with [`MaskAction::Invalidate`] are
+    /// also their positions afterwards.
+    pub fn apply_revolution(&self, revolution: &mut Revolution) -> Vec<usize> {
+        let samples = std::mem::take(&mut revolution.samples);
+        let mut masked = Vec::new();
+        for (i, sample) in samples.into_iter().enumerate() {
+            if !self.is_masked(&sample) {
+                revolution.samples.push(sample);
+                continue;
+            }
+            masked.push(i);
+            revolution.samples.extend(self.apply(sample));
+        }
+        masked
+    }
+}
+
+/// Even-odd test of a point against a polygon
+fn contains(polygon: &[Point2], p: Point2) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[j];
+        if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// A [`Mask`] that can be reconfigured while scans are being filtered
+///
+/// Clones share the same mask, so one can be kept for configuration while another filters.
+#[derive(Debug, Clone, Default)]
+pub struct MaskFilter {
+    mask: Arc<RwLock<Mask>>,
+}
+
+impl MaskFilter {
+    pub fn new(mask: Mask) -> MaskFilter {
+        MaskFilter {
+            mask: Arc::new(RwLock::new(mask)),
+        }
+    }
+
+    /// The current mask
+    pub fn mask(&self) -> RwLockReadGuard<'_, Mask> {
+        self.mask.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Replaces the mask
+    pub fn set(&self, mask: Mask) {
+        self.update(|m| *m = mask);
+    }
+
+    /// Changes the mask in place, e.g. to add a sector
+    pub fn update(&self, f: impl FnOnce(&mut Mask)) {
+        f(&mut self.mask.write().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    /// Passes a sample through the current mask, `None` if it was dropped
+    pub fn apply(&self, sample: Sample) -> Option<Sample> {
+        self.mask().apply(sample)
+    }
+
+    /// Masks a sample stream
+    pub fn filter<I: IntoIterator<Item = Sample>>(&self, samples: I) -> impl Iterator<Item = Sample> {
+        let filter = self.clone();
+        samples.into_iter().filter_map(move |s| filter.apply(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revolution(angles: &[f32]) -> Revolution {
+        Revolution {
+            samples: angles.iter().map(|&angle| Sample::at(angle, 1000.0)).collect(),
+        }
+    }
+
+    #[test]
+    fn sectors_wrap_through_zero() {
+        let sector = Sector::new(-10.0, 10.0);
+        assert_eq!(sector, Sector { start: 350.0, end: 10.0 });
+        assert!(sector.contains(355.0) && sector.contains(0.0) && sector.contains(10.0));
+        assert!(!sector.contains(11.0) && !sector.contains(180.0) && !sector.contains(349.0));
+
+        let sector = Sector::new(350.0, 10.0);
+        assert!(sector.contains(-5.0) && sector.contains(365.0));
+    }
+
+    #[test]
+    fn full_spans_cover_the_circle() {
+        for sector in [Sector::new(0.0, 360.0), Sector::new(90.0, 450.0), Sector::new(-180.0, 270.0)] {
+            assert!([0.0, 90.0, 180.0, 359.9].iter().all(|&a| sector.contains(a)), "{sector:?}");
+        }
+        // an empty span is not a full one
+        assert!(!Sector::new(90.0, 90.0).contains(100.0));
+    }
+
+    #[test]
+    fn reports_masked_samples() {
+        let mut mask = Mask {
+            sectors: vec![Sector::new(350.0, 10.0)],
+            ..Mask::default()
+        };
+        let mut dropped = revolution(&[0.0, 90.0, 180.0, 355.0]);
+        assert_eq!(mask.apply_revolution(&mut dropped), vec![0, 3]);
+        assert_eq!(dropped.samples.iter().map(Sample::angle).collect::<Vec<_>>(), vec![90.0, 180.0]);
+
+        mask.action = MaskAction::Invalidate;
+        let mut invalidated = revolution(&[0.0, 90.0, 180.0, 355.0]);
+        assert_eq!(mask.apply_revolution(&mut invalidated), vec![0, 3]);
+        assert_eq!(invalidated.len(), 4);
+        assert!(!invalidated.samples[0].is_valid() && !invalidated.samples[3].is_valid());
+        assert_eq!(invalidated.samples[0].intensity(), Some(0));
+        assert!(invalidated.samples[1].is_valid());
+    }
+
+    #[test]
+    fn masks_hits_on_the_footprint() {
+        // a 40 cm square robot with the lidar at its centre
+        let mask = Mask {
+            footprint: vec![
+                Point2::new(0.2, 0.2),
+                Point2::new(-0.2, 0.2),
+                Point2::new(-0.2, -0.2),
+                Point2::new(0.2, -0.2),
+            ],
+            ..Mask::default()
+        };
+        assert!(mask.is_masked(&Sample::at(45.0, 250.0)));
+        assert!(!mask.is_masked(&Sample::at(0.0, 250.0)));
+        // no return is not a hit on the robot
+        assert!(!mask.is_masked(&Sample::at(0.0, 0.0)));
+    }
+}
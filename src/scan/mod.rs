@@ -0,0 +1,21 @@
+//! Processing of decoded scans, from raw samples to robot-frame geometry
+
+pub mod deskew;
+pub mod filter;
+pub mod geometry;
+pub mod grid;
+pub mod mask;
+pub mod mounting;
+pub mod outlier;
+pub mod temporal;
+
+pub use deskew::{Deskew, Motion, Odometry, Twist};
+pub use filter::{
+    FilterChain, InvalidFilter, QualityFilter, RangeFilter, RangePolicy, SampleFilter, StageStats,
+};
+pub use geometry::{AngleDirection, Point2, Pose2};
+pub use grid::{BinPolicy, EmptyBin, LaserScan, Resampler};
+pub use mask::{Mask, MaskAction, MaskFilter, Sector};
+pub use mounting::Mounting;
+pub use outlier::{MedianFilter, RevolutionFilter, ShadowFilter, StatisticalOutlierFilter};
+pub use temporal::{TemporalFilter, TemporalMode};
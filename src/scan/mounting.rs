@@ -0,0 +1,118 @@
+use crate::laser::{Revolution, Sample};
+use crate::scan::geometry::{wrap_pi, AngleDirection, Point2, Pose2};
+
+/// Where the lidar sits on the robot
+///
+/// Positions are in the robot's base frame (metres, x forward, y left), `yaw` turns the lidar's
+/// zero angle counter-clockwise away from the robot's forward axis (radians).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Mounting {
+    pub x: f64,
+    pub y: f64,
+    pub yaw: f64,
+    /// mounted upside down, so that its clockwise scan appears counter-clockwise from above
+    pub upside_down: bool,
+}
+
+impl Mounting {
+    /// The lidar's pose in the base frame
+    pub fn pose(&self) -> Pose2 {
+        Pose2::new(self.x, self.y, self.yaw)
+    }
+
+    /// Point of a sample in the lidar's frame, with the lidar's top facing up
+    pub fn sensor_point(&self, sample: &Sample) -> Point2 {
+        let p = Point2::from_sample(sample);
+        if self.upside_down {
+            Point2::new(p.x, -p.y)
+        } else {
+            p
+        }
+    }
+
+    /// Point of a sample in the base frame
+    pub fn to_base(&self, sample: &Sample) -> Point2 {
+        self.pose().transform(self.sensor_point(sample))
+    }
+
+    /// Direction of a sample relative to the robot's axes, in the units of `direction`
+    pub fn base_angle(&self, sample: &Sample, direction: AngleDirection) -> f64 {
+        let mut angle = AngleDirection::CounterClockwise.from_device(sample.angle());
+        if self.upside_down {
+            angle = -angle;
+        }
+        let angle = wrap_pi(angle + self.yaw);
+
+        match direction {
+            AngleDirection::CounterClockwise => angle,
+            AngleDirection::Clockwise => AngleDirection::CounterClockwise.to_device(angle) as f64,
+        }
+    }
+
+    /// Base frame points of a revolution's valid samples
+    pub fn points(&self, revolution: &Revolution) -> Vec<Point2> {
+        revolution
+            .samples
+            .iter()
+            .filter(|s| s.is_valid())
+            .map(|s| self.to_base(s))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    fn close(a: Point2, b: Point2) -> bool {
+        (a.x - b.x).abs() < 1e-6 && (a.y - b.y).abs() < 1e-6
+    }
+
+    #[test]
+    fn maps_samples_into_the_base_frame_and_back() {
+        // 20 cm ahead of the base, turned to face left
+        let mounting = Mounting { x: 0.2, y: 0.0, yaw: FRAC_PI_2, upside_down: false };
+        let sample = Sample::at(90.0, 1000.0);
+
+        // 90° clockwise of the lidar's front is the robot's front
+        let base = mounting.to_base(&sample);
+        assert!(close(base, Point2::new(1.2, 0.0)), "{base:?}");
+
+        let back = mounting.pose().inverse().transform(base);
+        assert!(close(back, mounting.sensor_point(&sample)), "{back:?}");
+        let (angle, distance) = back.to_polar();
+        assert!((angle - 90.0).abs() < 1e-3 && (distance - 1000.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn mirrors_an_upside_down_lidar() {
+        let mounting = Mounting { upside_down: true, ..Mounting::default() };
+        let sample = Sample::at(90.0, 1000.0);
+
+        // turning clockwise from below is turning counter-clockwise from above
+        assert!(close(mounting.to_base(&sample), Point2::new(0.0, 1.0)));
+        assert!((mounting.base_angle(&sample, AngleDirection::CounterClockwise) - FRAC_PI_2).abs() < 1e-6);
+        assert!((mounting.base_angle(&sample, AngleDirection::Clockwise) - 270.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn turns_angles_by_the_mounting_yaw() {
+        let mounting = Mounting { yaw: -FRAC_PI_2, ..Mounting::default() };
+        let sample = Sample::at(0.0, 1000.0);
+
+        // a lidar facing right sees the robot's right at its zero angle
+        let angle = mounting.base_angle(&sample, AngleDirection::CounterClockwise);
+        assert!((angle + FRAC_PI_2).abs() < 1e-6);
+        assert!((mounting.base_angle(&sample, AngleDirection::Clockwise) - 90.0).abs() < 1e-3);
+        assert_eq!(AngleDirection::CounterClockwise.to_device(angle), 90.0);
+    }
+
+    #[test]
+    fn skips_invalid_samples() {
+        let revolution = Revolution {
+            samples: vec![Sample::at(0.0, 1000.0), Sample::at(90.0, 0.0)],
+        };
+        assert_eq!(Mounting::default().points(&revolution), vec![Point2::new(1.0, 0.0)]);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::laser::Revolution;
+use crate::scan::filter::FilterChain;
+use crate::scan::geometry::Point2;
+
+/// A filter that needs a whole revolution, e.g. to look at neighbouring samples
+pub trait RevolutionFilter: Send {
+    fn name(&self) -> &str;
+
+    /// Filters a revolution in place, returning how many samples were removed
+    fn apply(&mut self, revolution: &mut Revolution) -> usize;
+}
+
+impl RevolutionFilter for FilterChain {
+    fn name(&self) -> &str {
+        "chain"
+    }
+
+    fn apply(&mut self, revolution: &mut Revolution) -> usize {
+        let before = revolution.len();
+        self.apply_revolution(revolution);
+        before - revolution.len()
+    }
+}
+
+/// Indices of the samples with a valid distance
+fn valid(revolution: &Revolution) -> Vec<usize> {
+    (0..revolution.len())
+        .filter(|&i| revolution.samples[i].is_valid())
+        .collect()
+}
+
+/// Removes the samples whose index is marked
+fn remove_marked(revolution: &mut Revolution, marked: &[bool]) -> usize {
+    let mut i = 0;
+    revolution.samples.retain(|_| {
+        i += 1;
+        !marked[i - 1]
+    });
+    marked.iter().filter(|&&m| m).count()
+}
+
+/// Replaces each distance by the median of its angular neighbourhood, flattening single-sample
+/// spikes. Invalid samples are left alone and don't take part.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MedianFilter {
+    /// neighbours considered on each side
+    pub half_window: usize,
+}
+
+impl RevolutionFilter for MedianFilter {
+    fn name(&self) -> &str {
+        "median"
+    }
+
+    fn apply(&mut self, revolution: &mut Revolution) -> usize {
+        let valid = valid(revolution);
+        let n = valid.len();
+        if n <= 2 * self.half_window {
+            return 0;
+        }
+
+        let distances: Vec<f32> = valid.iter().map(|&i| revolution.samples[i].distance()).collect();
+        let mut window = Vec::with_capacity(2 * self.half_window + 1);
+        for (k, &i) in valid.iter().enumerate() {
+            window.clear();
+            // the scan is circular, so the window wraps around
+            window.extend((0..=2 * self.half_window).map(|o| distances[(k + n + o - self.half_window) % n]));
+            window.sort_by(f32::total_cmp);
+            revolution.samples[i].set_distance(window[self.half_window]);
+        }
+
+        0
+    }
+}
+
+/// Removes points whose mean distance to their neighbours is unusually large compared to the
+/// rest of the revolution (mean + `std_ratio` standard deviations).
+///
+/// Neighbours are the `neighbours` valid samples on either side, which in a 2D scan are also the
+/// nearest ones in space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatisticalOutlierFilter {
+    pub neighbours: usize,
+    pub std_ratio: f64,
+}
+
+impl RevolutionFilter for StatisticalOutlierFilter {
+    fn name(&self) -> &str {
+        "statistical outlier"
+    }
+
+    fn apply(&mut self, revolution: &mut Revolution) -> usize {
+        let valid = valid(revolution);
+        let n = valid.len();
+        if n <= 2 * self.neighbours || self.neighbours == 0 {
+            return 0;
+        }
+
+        let points: Vec<Point2> = valid.iter().map(|&i| Point2::from_sample(&revolution.samples[i])).collect();
+        let means: Vec<f64> = (0..n)
+            .map(|k| {
+                let total: f64 = (1..=self.neighbours)
+                    .flat_map(|o| [(k + o) % n, (k + n - o) % n])
+                    .map(|j| (points[j].x - points[k].x).hypot(points[j].y - points[k].y))
+                    .sum();
+                total / (2 * self.neighbours) as f64
+            })
+            .collect();
+
+        let mean = means.iter().sum::<f64>() / n as f64;
+        let std = (means.iter().map(|m| (m - mean).powi(2)).sum::<f64>() / n as f64).sqrt();
+        let threshold = mean + self.std_ratio * std;
+
+        let mut marked = vec![false; revolution.len()];
+        for (k, &i) in valid.iter().enumerate() {
+            marked[i] = means[k] > threshold;
+        }
+        remove_marked(revolution, &marked)
+    }
+}
+
+/// Removes veiling points: mixed measurements smeared between a foreground edge and the
+/// background behind it.
+///
+/// Two neighbours seen at a grazing incidence (the segment between them nearly parallel to the
+/// beam) can't both be real surfaces, so the farther one is removed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowFilter {
+    /// smallest plausible angle between beam and surface (degrees)
+    pub min_angle: f64,
+    /// largest plausible angle between beam and surface (degrees)
+    pub max_angle: f64,
+    /// neighbours checked on each side
+    pub window: usize,
+}
+
+impl Default for ShadowFilter {
+    fn default() -> Self {
+        ShadowFilter {
+            min_angle: 10.0,
+            max_angle: 170.0,
+            window: 1,
+        }
+    }
+}
+
+impl RevolutionFilter for ShadowFilter {
+    fn name(&self) -> &str {
+        "shadow"
+    }
+
+    fn apply(&mut self, revolution: &mut Revolution) -> usize {
+        let valid = valid(revolution);
+        let n = valid.len();
+        let mut marked = vec![false; revolution.len()];
+
+        for k in 0..n {
+            for o in 1..=self.window.min(n.saturating_sub(1)) {
+                let (i, j) = (valid[k], valid[(k + o) % n]);
+                let (a, b) = (&revolution.samples[i], &revolution.samples[j]);
+                let (r1, r2) = (a.distance() as f64, b.distance() as f64);
+                let delta = ((b.angle() - a.angle()) as f64).to_radians();
+
+                let angle = (r2 * delta.sin()).atan2(r1 - r2 * delta.cos()).to_degrees().abs();
+                if angle < self.min_angle || angle > self.max_angle {
+                    marked[if r1 > r2 { i } else { j }] = true;
+                }
+            }
+        }
+
+        remove_marked(revolution, &marked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::laser::Sample;
+
+    /// A revolution with one sample per degree, from 0°
+    fn revolution(distances: impl IntoIterator<Item = f32>) -> Revolution {
+        let samples = distances
+            .into_iter()
+            .enumerate()
+            .map(|(i, distance)| Sample::at(i as f32, distance))
+            .collect();
+        Revolution { samples }
+    }
+
+    fn distances(revolution: &Revolution) -> Vec<f32> {
+        revolution.samples.iter().map(Sample::distance).collect()
+    }
+
+    #[test]
+    fn median_flattens_spikes() {
+        let mut spiky = revolution([1000.0, 1000.0, 5000.0, 0.0, 1000.0, 1010.0, 1020.0]);
+        let removed = MedianFilter { half_window: 1 }.apply(&mut spiky);
+
+        assert_eq!(removed, 0);
+        // the invalid sample neither changes nor counts as a neighbour
+        assert_eq!(distances(&spiky), vec![1000.0, 1000.0, 1000.0, 0.0, 1010.0, 1010.0, 1010.0]);
+    }
+
+    #[test]
+    fn median_leaves_short_revolutions_alone() {
+        let mut short = revolution([1000.0, 5000.0, 0.0]);
+        MedianFilter { half_window: 1 }.apply(&mut short);
+        assert_eq!(distances(&short), vec![1000.0, 5000.0, 0.0]);
+    }
+
+    #[test]
+    fn statistical_filter_removes_stray_points() {
+        // a round room with one stray return
+        let mut room = revolution((0..360).map(|i| if i == 90 { 3000.0 } else { 1000.0 }));
+        // its neighbours are pulled up to a quarter of its mean distance, which a tight
+        // threshold would catch as well
+        let filter = &mut StatisticalOutlierFilter { neighbours: 2, std_ratio: 5.0 };
+
+        assert_eq!(filter.apply(&mut room), 1);
+        assert_eq!(room.len(), 359);
+        assert!(distances(&room).iter().all(|&d| d == 1000.0));
+    }
+
+    #[test]
+    fn shadow_filter_removes_veiling_points() {
+        // a wall in front, the background behind it, and a mixed return in between
+        let mut edge = revolution((0..360).map(|i| match i {
+            0..180 => 1000.0,
+            180 => 2000.0,
+            _ => 3000.0,
+        }));
+        let removed = ShadowFilter::default().apply(&mut edge);
+
+        // both edges seen at a grazing incidence lose their far side
+        assert_eq!(removed, 3);
+        let angles: Vec<f32> = edge.samples.iter().map(Sample::angle).collect();
+        assert!(angles.contains(&179.0) && angles.contains(&0.0));
+        assert!(!angles.contains(&180.0) && !angles.contains(&181.0) && !angles.contains(&359.0));
+    }
+}
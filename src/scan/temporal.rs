@@ -0,0 +1,207 @@
+use crate::laser::{Revolution, Sample};
+use crate::scan::outlier::RevolutionFilter;
+use std::collections::VecDeque;
+
+/// How a [`TemporalFilter`] combines the ranges of consecutive revolutions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TemporalMode {
+    /// Mean over the last `n` revolutions
+    MovingAverage(usize),
+    /// Exponential smoothing, weighting the newest revolution by `alpha` in `(0, 1]`
+    Exponential(f32),
+    /// Median over the last `n` revolutions
+    Median(usize),
+}
+
+/// Per-bin state
+#[derive(Debug, Clone, Default)]
+struct Bin {
+    /// recent ranges, NaN where a revolution had no valid sample in the bin
+    history: VecDeque<f32>,
+    smoothed: Option<f32>,
+    /// most recent sample of the bin, the template for its output
+    last: Option<Sample>,
+}
+
+/// Smooths ranges over consecutive revolutions of a static scene.
+///
+/// Samples are grouped into equal angular bins; each revolution contributes the mean of its valid
+/// samples per bin. The output revolution holds one sample per bin that has data, at the bin's
+/// centre angle.
+#[derive(Debug, Clone)]
+pub struct TemporalFilter {
+    mode: TemporalMode,
+    bins: Vec<Bin>,
+}
+
+impl TemporalFilter {
+    /// A filter over `bins` equal sectors of the full circle
+    ///
+    /// # Panics
+    ///
+    /// If an exponential mode's `alpha` is not in `(0, 1]`.
+    pub fn new(mode: TemporalMode, bins: usize) -> TemporalFilter {
+        if let TemporalMode::Exponential(alpha) = mode {
+            assert!(alpha > 0.0 && alpha <= 1.0, "smoothing factor {alpha} is not in (0, 1]");
+        }
+        TemporalFilter {
+            mode,
+            bins: vec![Bin::default(); bins.max(1)],
+        }
+    }
+
+    pub fn mode(&self) -> TemporalMode {
+        self.mode
+    }
+
+    /// Forgets all previous revolutions
+    pub fn reset(&mut self) {
+        self.bins.fill(Bin::default());
+    }
+
+    fn resolution(&self) -> f32 {
+        360.0 / self.bins.len() as f32
+    }
+
+    /// Adds a revolution and returns the smoothed profile
+    pub fn smooth(&mut self, revolution: &Revolution) -> Revolution {
+        let resolution = self.resolution();
+        let mut sums = vec![(0.0f32, 0usize); self.bins.len()];
+
+        for sample in &revolution.samples {
+            let i = ((sample.angle() / resolution) as usize).min(self.bins.len() - 1);
+            if sample.is_valid() {
+                sums[i].0 += sample.distance();
+                sums[i].1 += 1;
+            }
+            self.bins[i].last = Some(sample.clone());
+        }
+
+        let mut samples = Vec::new();
+        for (i, (bin, (sum, count))) in self.bins.iter_mut().zip(sums).enumerate() {
+            let range = if count > 0 { sum / count as f32 } else { f32::NAN };
+            let Some(distance) = bin.push(self.mode, range) else {
+                continue;
+            };
+            let Some(mut sample) = bin.last.clone() else {
+                continue;
+            };
+
+            sample.start = samples.is_empty();
+            sample.set_angle((i as f32 + 0.5) * resolution);
+            sample.set_distance(distance);
+            samples.push(sample);
+        }
+
+        Revolution { samples }
+    }
+}
+
+impl Bin {
+    /// Records a revolution's range (NaN if none) and returns the combined one
+    fn push(&mut self, mode: TemporalMode, range: f32) -> Option<f32> {
+        match mode {
+            TemporalMode::Exponential(alpha) => {
+                if !range.is_nan() {
+                    self.smoothed = Some(match self.smoothed {
+                        Some(prev) => prev + alpha * (range - prev),
+                        None => range,
+                    });
+                }
+                self.smoothed
+            }
+            TemporalMode::MovingAverage(n) | TemporalMode::Median(n) => {
+                self.history.push_back(range);
+                while self.history.len() > n.max(1) {
+                    self.history.pop_front();
+                }
+
+                let mut valid: Vec<f32> = self.history.iter().copied().filter(|r| !r.is_nan()).collect();
+                if valid.is_empty() {
+                    return None;
+                }
+                if let TemporalMode::Median(_) = mode {
+                    valid.sort_by(f32::total_cmp);
+                    return Some(valid[valid.len() / 2]);
+                }
+                Some(valid.iter().sum::<f32>() / valid.len() as f32)
+            }
+        }
+    }
+}
+
+/// Replaces the revolution by its smoothed profile. Like the [`MedianFilter`], this changes
+/// ranges rather than removing samples, so it reports none removed.
+///
+/// [`MedianFilter`]: crate::scan::MedianFilter
+impl RevolutionFilter for TemporalFilter {
+    fn name(&self) -> &str {
+        "temporal"
+    }
+
+    fn apply(&mut self, revolution: &mut Revolution) -> usize {
+        *revolution = self.smooth(revolution);
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A revolution with one sample per degree, all at `distance`
+    fn revolution(distance: f32) -> Revolution {
+        Revolution {
+            samples: (0..360).map(|i| Sample::at(i as f32, distance)).collect(),
+        }
+    }
+
+    #[test]
+    fn exponential_smoothing_converges() {
+        let mut filter = TemporalFilter::new(TemporalMode::Exponential(0.5), 4);
+        assert_eq!(filter.smooth(&revolution(1000.0)).samples[0].distance(), 1000.0);
+
+        // each revolution halves the remaining gap to the new range
+        let distances: Vec<f32> = (0..12)
+            .map(|_| filter.smooth(&revolution(2000.0)).samples[0].distance())
+            .collect();
+        assert_eq!(distances[..3], [1500.0, 1750.0, 1875.0]);
+        assert!(2000.0 - distances[11] <= 0.25, "{distances:?}");
+    }
+
+    #[test]
+    fn bins_hold_their_range_through_missing_returns() {
+        let mut filter = TemporalFilter::new(TemporalMode::MovingAverage(2), 4);
+        filter.smooth(&revolution(1000.0));
+        let smoothed = filter.smooth(&revolution(0.0));
+
+        // one sample per bin, at its centre, still at the last valid range
+        let bins: Vec<_> = smoothed.samples.iter().map(|s| (s.angle(), s.distance())).collect();
+        assert_eq!(bins, vec![(45.0, 1000.0), (135.0, 1000.0), (225.0, 1000.0), (315.0, 1000.0)]);
+        assert!(smoothed.samples[0].start());
+    }
+
+    #[test]
+    fn median_ignores_a_single_bad_revolution() {
+        let mut filter = TemporalFilter::new(TemporalMode::Median(3), 1);
+        let distances: Vec<f32> = [1000.0, 1010.0, 5000.0, 990.0]
+            .into_iter()
+            .map(|distance| filter.smooth(&revolution(distance)).samples[0].distance())
+            .collect();
+        assert_eq!(distances, vec![1000.0, 1010.0, 1010.0, 1010.0]);
+    }
+
+    #[test]
+    fn reports_nothing_removed() {
+        let mut filter = TemporalFilter::new(TemporalMode::Exponential(1.0), 36);
+        let mut scan = revolution(1000.0);
+        assert_eq!(filter.apply(&mut scan), 0);
+        assert_eq!(scan.len(), 36);
+    }
+
+    #[test]
+    #[should_panic(expected = "not in (0, 1]")]
+    fn rejects_a_smoothing_factor_of_zero() {
+        TemporalFilter::new(TemporalMode::Exponential(0.0), 4);
+    }
+}
@@ -0,0 +1,247 @@
+//! Terminal scan viewer, for lidars only reachable over SSH
+//!
+//! Keys: `q`/`Esc` quit, `+`/`-` zoom, `0` reset zoom, `space` pause, `m` switch between
+//! standard and dense scans, `b` switch between braille and half-block rendering.
+
+use crate::laser::{Lidar, Revolution, Sample, ScanMode, ScanSession, SlLidarResponseDeviceInfoT};
+use crate::render::ramp;
+use crate::scan::Mounting;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::symbols::Marker;
+use ratatui::text::Line;
+use ratatui::widgets::canvas::{Canvas, Points};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::error::Error;
+use std::sync::mpsc::TryRecvError;
+use std::time::Duration;
+
+/// How long to wait for key presses between redraws
+const FRAME_TIME: Duration = Duration::from_millis(50);
+/// Colour steps used for intensities, each drawn as one layer
+const SHADES: u8 = 8;
+/// Zoom factor per key press
+const ZOOM: f64 = 1.25;
+/// Distance shown from the centre to the top and bottom edges before zooming, in metres
+const DEFAULT_RANGE: f64 = 8.0;
+/// Width of the side panel, in cells
+const PANEL_WIDTH: u16 = 36;
+
+/// Runs the viewer until `q` or `Esc` is pressed, restoring the terminal afterwards
+pub fn run(lidar: &mut Lidar) -> Result<(), Box<dyn Error>> {
+    let mut viewer = Viewer::new(lidar)?;
+    let mut terminal = ratatui::init();
+    let result = viewer.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+struct Viewer<'a> {
+    // the session must be dropped (stopped) before a new one starts
+    session: Option<ScanSession>,
+    lidar: &'a mut Lidar,
+    mode: ScanMode,
+    info: SlLidarResponseDeviceInfoT,
+    health: &'static str,
+    /// last full revolution
+    latest: Revolution,
+    /// revolution being received
+    building: Vec<Sample>,
+    paused: bool,
+    /// distance from the centre to the top and bottom edges, in metres
+    range: f64,
+    marker: Marker,
+    error: Option<String>,
+}
+
+impl<'a> Viewer<'a> {
+    fn new(lidar: &'a mut Lidar) -> Result<Viewer<'a>, Box<dyn Error>> {
+        let info = lidar.get_info()?;
+        let health = lidar.get_health_str()?;
+        let mut viewer = Viewer {
+            session: None,
+            lidar,
+            mode: ScanMode::Standard,
+            info,
+            health,
+            latest: Revolution::default(),
+            building: Vec::new(),
+            paused: false,
+            range: DEFAULT_RANGE,
+            marker: Marker::Braille,
+            error: None,
+        };
+        viewer.restart();
+        Ok(viewer)
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), Box<dyn Error>> {
+        loop {
+            self.receive();
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if !event::poll(FRAME_TIME)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('+') | KeyCode::Char('=') => self.range /= ZOOM,
+                KeyCode::Char('-') => self.range *= ZOOM,
+                KeyCode::Char('0') => self.range = DEFAULT_RANGE,
+                KeyCode::Char(' ') => self.paused = !self.paused,
+                KeyCode::Char('m') => {
+                    self.mode = match self.mode {
+                        ScanMode::Standard => ScanMode::Dense,
+                        ScanMode::Dense => ScanMode::Standard,
+                    };
+                    self.restart();
+                }
+                KeyCode::Char('b') => {
+                    self.marker = match self.marker {
+                        Marker::Braille => Marker::HalfBlock,
+                        _ => Marker::Braille,
+                    };
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Stops the current scan, if any, and starts one in the selected mode
+    fn restart(&mut self) {
+        self.session = None;
+        self.building.clear();
+        self.latest = Revolution::default();
+        match self.lidar.start_scan_mode(self.mode) {
+            Ok(session) => {
+                self.session = Some(session);
+                self.error = None;
+            }
+            Err(err) => self.error = Some(err.to_string()),
+        }
+    }
+
+    /// Takes in everything buffered so far, keeping the last full revolution
+    fn receive(&mut self) {
+        let Some(session) = &self.session else {
+            return;
+        };
+        loop {
+            match session.receiver().try_recv() {
+                Ok(sample) => {
+                    if sample.start && !self.building.is_empty() {
+                        let samples = std::mem::take(&mut self.building);
+                        if !self.paused {
+                            self.latest = Revolution { samples };
+                        }
+                    }
+                    // samples before the first start don't make up a revolution
+                    if sample.start || !self.building.is_empty() {
+                        self.building.push(sample);
+                    }
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.error = Some(match session.error() {
+                        Some(err) => err.to_string(),
+                        None => "Scan ended".to_string(),
+                    });
+                    self.session = None;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [scan, panel] =
+            Layout::horizontal([Constraint::Min(0), Constraint::Length(PANEL_WIDTH)]).areas(frame.area());
+        self.draw_scan(frame, scan);
+        self.draw_panel(frame, panel);
+    }
+
+    fn draw_scan(&self, frame: &mut Frame, area: Rect) {
+        // cells are about twice as high as wide, keep metres square
+        let aspect = area.width.saturating_sub(2) as f64 / (2.0 * area.height.saturating_sub(2).max(1) as f64);
+        let (x_range, y_range) = (self.range * aspect, self.range);
+
+        let mut layers: Vec<Vec<(f64, f64)>> = vec![Vec::new(); SHADES as usize];
+        let mounting = Mounting::default();
+        for sample in self.latest.samples.iter().filter(|s| s.is_valid()) {
+            // forward points right, left points up
+            let point = mounting.to_base(sample);
+            let shade = (sample.intensity.unwrap_or(u8::MAX) as usize * SHADES as usize / 64).min(SHADES as usize - 1);
+            layers[shade].push((point.x, point.y));
+        }
+
+        let title = format!(" {:?} scan, ±{:.1} m{} ", self.mode, self.range, if self.paused { ", paused" } else { "" });
+        let canvas = Canvas::default()
+            .block(Block::bordered().title(title))
+            .marker(self.marker)
+            .x_bounds([-x_range, x_range])
+            .y_bounds([-y_range, y_range])
+            .paint(|ctx| {
+                for (shade, coords) in layers.iter().enumerate() {
+                    let [r, g, b] = ramp((shade * 64 / SHADES as usize) as u8);
+                    ctx.draw(&Points {
+                        coords,
+                        color: Color::Rgb(r, g, b),
+                    });
+                }
+                ctx.print(0.0, 0.0, "+".yellow());
+            });
+        frame.render_widget(canvas, area);
+    }
+
+    fn draw_panel(&self, frame: &mut Frame, area: Rect) {
+        let heading = Style::default().add_modifier(Modifier::BOLD);
+        let info = &self.info;
+        let mut lines = vec![
+            Line::styled("Device", heading),
+            Line::from(format!("  model     {}", info.model_name())),
+            Line::from(format!("  firmware  {}.{}", info.firmware_version >> 8, info.firmware_version & 0xff)),
+            Line::from(format!("  hardware  {}", info.hardware_version)),
+            Line::from("  serial"),
+            Line::from(format!("  {}", info.serial_number_hex())),
+            Line::from(format!("  health    {}", self.health)),
+            Line::default(),
+            Line::styled("Scan", heading),
+            Line::from(format!("  mode      {:?}", self.mode)),
+        ];
+
+        if let Some(session) = &self.session {
+            let stats = session.stats();
+            let rate = format!("  samples   {:.0}/s of {:.0}/s", stats.sample_rate(), session.timing().samples_per_second());
+            lines.extend([
+                Line::from(format!("  rotation  {:.2} Hz", stats.rotation_hz())),
+                if stats.is_lagging() { Line::from(rate).red() } else { Line::from(rate) },
+                Line::from(format!("  points    {}", self.latest.len())),
+                Line::default(),
+                Line::styled("Errors", heading),
+                Line::from(format!("  dropped   {}", stats.dropped())),
+                Line::from(format!("  discarded {} B", stats.discarded_bytes())),
+                Line::from(format!("  resyncs   {}", stats.resyncs())),
+            ]);
+        }
+        if let Some(err) = &self.error {
+            lines.extend([Line::default(), Line::from(err.as_str()).red()]);
+        }
+
+        lines.extend([
+            Line::default(),
+            Line::styled("Keys", heading),
+            Line::from("  +/- zoom   0 reset   space pause"),
+            Line::from("  m mode     b marker  q quit"),
+        ]);
+
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" rangefinder ")), area);
+    }
+}